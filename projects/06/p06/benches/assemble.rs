@@ -0,0 +1,90 @@
+// Benchmark of assembling 500k generated lines in the style of translated
+// Jack code as one input, which overflows ROM: `cargo bench`
+// Author: Leo Robinovitch
+
+use std::path::Path;
+use std::io;
+use std::time::{Duration, Instant};
+
+use p06::{assemble_sources, ErrorKind, Options, Source};
+
+/// Lines generated
+const LINES: usize = 500_000;
+
+/// Times the input is assembled, keeping the fastest
+const RUNS: usize = 5;
+
+
+/// Returns a program of at least `lines` lines: pushes and pops of static
+/// variables, comparisons with labels and calls through return labels,
+/// with comments and indentation
+///
+/// # Arguments
+///
+/// * `lines` - lines to generate
+fn generate(lines: usize) -> String {
+    let mut asm = String::with_capacity(lines * 16);
+    let mut count = 0;
+    let mut block = 0;
+    while count < lines {
+        asm.push_str(&format!("// block {}\n\
+            (Main.f{block})\n\
+            \x20   @Main.{var} // push static\n\
+            \x20   D=M\n\
+            \x20   @SP\n\
+            \x20   A=M\n\
+            \x20   M=D\n\
+            \x20   @SP\n\
+            \x20   M=M+1\n\
+            \x20   @SP\n\
+            \x20   AM=M-1\n\
+            \x20   D=M\n\
+            \x20   @Main.{var}$if{block}\n\
+            \x20   D;JNE\n\
+            \x20   @Main.f{block}\n\
+            \x20   0;JMP\n\
+            (Main.{var}$if{block})\n\
+            \x20   @Main.f{block}$ret\n\
+            \x20   D=A\n\
+            \x20   @R13\n\
+            \x20   M=D\n\
+            (Main.f{block}$ret)\n",
+            block, block = block, var = block % 4096));
+        count += 21;
+        block += 1;
+    }
+    asm
+}
+
+
+/// Returns the fastest of RUNS runs of `f` and its last result
+///
+/// # Arguments
+///
+/// * `f` - the stage to time
+fn fastest<T>(f: &dyn Fn() -> T) -> (Duration, T) {
+    let mut best = Duration::from_secs(u64::MAX);
+    let mut result = None;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let output = f();
+        best = best.min(start.elapsed());
+        result = Some(output); // drops the previous result outside the timing
+    }
+    (best, result.unwrap())
+}
+
+
+fn main() {
+    let sources = [Source { name: "Main.asm".to_string(), contents: generate(LINES) }];
+    let lines = sources[0].contents.lines().count();
+    let load = |_: &Path| Err(io::Error::new(io::ErrorKind::NotFound, "not found"));
+
+    // every line is still parsed and encoded past the end of ROM, which is
+    // the only error reported
+    let (time, errors) = fastest(&|| assemble_sources(&sources, &load, &Options::default()).unwrap_err());
+    assert_eq!(vec![ErrorKind::RomFull], errors.iter().map(|e| e.kind).collect::<Vec<ErrorKind>>());
+
+    println!("assemble: {} lines in {:.1} ms ({:.2} M lines/s)",
+        lines, time.as_secs_f64() * 1e3, lines as f64 / time.as_secs_f64() / 1e6);
+}
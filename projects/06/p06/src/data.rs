@@ -0,0 +1,271 @@
+// Data directives, which fill RAM before the program runs
+// Author: Leo Robinovitch
+//
+// .data SCREEN+32        // following words are stored from this address
+// .word 1, -1, LOOP      // one word per expression
+// .string "HI"           // one word per character, then a 0
+//
+// Hack has no loadable data segment, so the assembler turns the words into
+// a prologue at ROM address 0 that stores each one, then jumps to the
+// program's first instruction.
+
+use crate::{check_a_value, expr, ErrorKind, LineError, SourceLine};
+
+/// Instructions in the prologue for each data word
+pub const WORD_SIZE: usize = 4;
+
+/// Instructions that end the prologue by jumping to the program
+pub const JUMP_SIZE: usize = 2;
+
+
+/// A parsed data directive. Expressions are slices of the parsed line.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Directive<'a> {
+    /// `.data ADDRESS`: the RAM address of the next word
+    Data(&'a str),
+    /// `.word EXPR, ...`: a word for each expression
+    Word(Vec<&'a str>),
+    /// `.string "TEXT"`: a word for each character and a terminating 0
+    String(&'a str),
+}
+
+impl<'a> Directive<'a> {
+    /// Returns the number of words the directive stores
+    pub fn word_count(&self) -> usize {
+        match self {
+            Directive::Data(_) => 0,
+            Directive::Word(exprs) => exprs.len(),
+            Directive::String(text) => text.chars().count() + 1,
+        }
+    }
+}
+
+
+/// Checks if a stripped line is a data directive
+///
+/// # Arguments
+///
+/// * `command` - the stripped line
+pub fn is_directive(command: &str) -> bool {
+    [".data", ".word", ".string"].iter()
+        .any(|name| command.starts_with(name) && !command[name.len()..].starts_with(crate::parser::is_symbol_char))
+}
+
+#[test]
+fn test_is_directive() {
+    assert!(is_directive(".data 16"));
+    assert!(is_directive(".word 1"));
+    assert!(is_directive(".string \"HI\""));
+    assert!(!is_directive(".words 1"));
+    assert!(!is_directive(".equ X 1"));
+}
+
+
+/// Splits the operands of `.word` at commas outside character literals
+///
+/// # Arguments
+///
+/// * `operands` - text after `.word`
+fn split_operands(operands: &str) -> Vec<&str> {
+    let mut exprs: Vec<&str> = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (idx, c) in operands.char_indices() {
+        match c {
+            '\'' => quoted = !quoted,
+            ',' if !quoted => {
+                exprs.push(operands[start..idx].trim());
+                start = idx + 1;
+            },
+            _ => {}
+        }
+    }
+    exprs.push(operands[start..].trim());
+    exprs
+}
+
+#[test]
+fn test_split_operands() {
+    assert_eq!(vec!["1", "2", "LOOP+1"], split_operands(" 1, 2 ,LOOP+1"));
+    assert_eq!(vec!["','", "'a'"], split_operands("',', 'a'"));
+    assert_eq!(vec!["1", ""], split_operands("1,"));
+}
+
+
+/// Parses a data directive, e.g. `.word 1, 2`
+///
+/// # Arguments
+///
+/// * `command` - the stripped line, starting with `.data`, `.word` or `.string`
+pub fn parse_directive(command: &str) -> Result<Directive<'_>, LineError> {
+    let invalid = |text: &str| LineError::new(ErrorKind::InvalidData, command, text);
+    let (name, operands) = command.split_at(command.find(char::is_whitespace).unwrap_or(command.len()));
+    let operands = operands.trim();
+    if operands.is_empty() {
+        return Err(invalid(command));
+    }
+
+    match name {
+        ".data" => Ok(Directive::Data(operands)),
+        ".word" => {
+            let exprs = split_operands(operands);
+            match exprs.iter().find(|expr| expr.is_empty()) {
+                Some(_) => Err(invalid(operands)),
+                None => Ok(Directive::Word(exprs))
+            }
+        },
+        ".string" if operands.len() >= 2 && operands.starts_with('"') && operands.ends_with('"') => {
+            Ok(Directive::String(&operands[1..operands.len() - 1]))
+        },
+        _ => Err(invalid(command))
+    }
+}
+
+#[test]
+fn test_parse_directive() {
+    assert_eq!(Ok(Directive::Data("SCREEN + 32")), parse_directive(".data SCREEN + 32"));
+    assert_eq!(Ok(Directive::Word(vec!["1", "-1", "LOOP"])), parse_directive(".word 1, -1, LOOP"));
+    assert_eq!(Ok(Directive::String("say \"hi\"")), parse_directive(".string \"say \"hi\"\""));
+    assert_eq!(Ok(Directive::String("")), parse_directive(".string \"\""));
+    assert_eq!(Directive::String("HI").word_count(), 3);
+
+    let error = parse_directive(".word 1,,2").unwrap_err();
+    assert_eq!((ErrorKind::InvalidData, 6, "1,,2"), (error.kind, error.offset, error.text.as_str()));
+    assert!(parse_directive(".data").is_err());
+    assert!(parse_directive(".string HI").is_err());
+}
+
+
+/// Returns the number of instructions in the prologue, which is left out
+/// when the directives store no words
+///
+/// # Arguments
+///
+/// * `directives` - parsed data directives with their source and stripped lines
+pub fn prologue_size(directives: &[(&SourceLine, &str, Directive)]) -> usize {
+    let words: usize = directives.iter().map(|(_, _, directive)| directive.word_count()).sum();
+    if words == 0 { 0 } else { words * WORD_SIZE + JUMP_SIZE }
+}
+
+
+/// Returns the prologue instructions that store `value` at `address`
+///
+/// # Arguments
+///
+/// * `address` - RAM address, at most 32767
+/// * `value` - the 16 bit word to store
+pub fn store(address: u16, value: u16) -> [u16; WORD_SIZE] {
+    // @value / D=A, or @!value / D=!A for words with the top bit set
+    let load = if value & 0x8000 == 0 {
+        [value, 0b1110110000010000]
+    } else {
+        [!value, 0b1110110001010000]
+    };
+    // @address / M=D
+    [load[0], load[1], address, 0b1110001100001000]
+}
+
+#[test]
+fn test_store() {
+    let asm = |words: [u16; WORD_SIZE]| {
+        let hack: Vec<String> = words.iter().map(|word| format!("{:016b}", word)).collect();
+        crate::disassemble::disassemble(&hack.join("\n")).unwrap()
+    };
+    assert_eq!("@65\nD=A\n@16384\nM=D\n", asm(store(16384, 'A' as u16)));
+    assert_eq!("@0\nD=!A\n@16\nM=D\n", asm(store(16, 0xffff)));
+    assert_eq!("@32767\nD=!A\n@16\nM=D\n", asm(store(16, 0x8000)));
+}
+
+
+/// Returns the instructions that end the prologue by jumping to the
+/// program's first instruction
+///
+/// # Arguments
+///
+/// * `start` - ROM address of the program's first instruction
+pub fn jump(start: u16) -> [u16; JUMP_SIZE] {
+    // @start / 0;JMP
+    [start, 0b1110101010000111]
+}
+
+
+/// Evaluates the data directives in order, returning the address and
+/// value of each word
+///
+/// # Arguments
+///
+/// * `directives` - parsed data directives with their source and stripped lines
+/// * `lookup` - returns the value of a symbol used in a line, if it is defined
+/// * `errors` - errors found are pushed here, located in the source
+pub fn evaluate<'a>(
+    directives: &[(&'a SourceLine, &'a str, Directive)],
+    lookup: &dyn Fn(&SourceLine, &str) -> Option<i32>,
+    errors: &mut Vec<crate::AssembleError>) -> Vec<(&'a SourceLine, u16, u16)> {
+
+    let mut words: Vec<(&SourceLine, u16, u16)> = Vec::new();
+    let mut address: Option<i32> = None;
+    let mut failed = false; // a .data address had an error, already reported
+    for (line, stripped_line, directive) in directives {
+        let value_of = |text: &str| expr::evaluate(text, &|symbol| lookup(line, symbol))
+            .map_err(|e| e.within(stripped_line, text));
+
+        let values: Vec<(&str, Result<i32, LineError>)> = match directive {
+            Directive::Data(text) => {
+                match value_of(text).and_then(|value| check_a_value(value, stripped_line, text)) {
+                    Ok(value) => address = Some(value as i32),
+                    Err(e) => {
+                        address = None;
+                        failed = true;
+                        errors.push(e.locate(line, stripped_line));
+                    }
+                }
+                continue;
+            },
+            Directive::Word(exprs) => exprs.iter().map(|text| (*text, value_of(text))).collect(),
+            Directive::String(text) => {
+                text.chars().map(|c| (*text, Ok(c as i32))).chain(std::iter::once((*text, Ok(0)))).collect()
+            }
+        };
+
+        let start = match address {
+            Some(start) => start,
+            None if failed => continue,
+            None => {
+                errors.push(LineError::new(ErrorKind::DataWithoutAddress, stripped_line, stripped_line)
+                    .locate(line, stripped_line));
+                continue;
+            }
+        };
+        for (offset, (text, value)) in values.into_iter().enumerate() {
+            let word_address = check_a_value(start + offset as i32, stripped_line, text);
+            let value = value.and_then(|value| match value {
+                -0x8000..=0xffff => Ok(value as u16),
+                _ => Err(LineError::new(ErrorKind::DataOutOfRange, stripped_line, text))
+            });
+            match (word_address, value) {
+                (Ok(word_address), Ok(value)) => words.push((line, word_address, value)),
+                (Err(e), _) | (_, Err(e)) => errors.push(e.locate(line, stripped_line))
+            }
+        }
+        address = address.map(|start| start + directive.word_count() as i32);
+    }
+    words
+}
+
+
+/// Returns the initialized RAM ranges of the data words, as first address
+/// and word count, merging words stored at consecutive addresses
+///
+/// # Arguments
+///
+/// * `words` - source line, address and value of each data word
+pub fn ranges(words: &[(&SourceLine, u16, u16)]) -> Vec<(u16, u16)> {
+    let mut ranges: Vec<(u16, u16)> = Vec::new();
+    for (_, address, _) in words {
+        match ranges.last_mut() {
+            Some((start, count)) if *start + *count == *address => *count += 1,
+            _ => ranges.push((*address, 1))
+        }
+    }
+    ranges
+}
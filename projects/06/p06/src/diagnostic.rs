@@ -0,0 +1,101 @@
+// Rustc-style rendering of assembler errors
+// Author: Leo Robinovitch
+
+use std::fmt::Display;
+
+use crate::AssembleError;
+use crate::lint::Warning;
+
+/// Returns the error rendered with its file name, line:column, the source
+/// line and a caret under the offending text, e.g.
+///
+/// ```text
+/// error: no mapping found for comp mnemonic `D+D`
+///   --> Fill.asm:50:11
+///    |
+/// 50 |         M=D+D
+///    |           ^^^
+/// ```
+///
+/// # Arguments
+///
+/// * `error` - the error to render
+pub fn render(error: &AssembleError) -> String {
+    render_message("error", &error.kind, &error.file, error.line, error.column, &error.text, &error.source)
+}
+
+
+/// Returns the warning rendered like an error, starting with `warning:`
+///
+/// # Arguments
+///
+/// * `warning` - the warning to render
+pub fn render_warning(warning: &Warning) -> String {
+    render_message("warning", &warning.kind, &warning.file, warning.line, warning.column,
+        &warning.text, &warning.source)
+}
+
+
+/// Returns a located message rendered with a caret under the offending text
+///
+/// # Arguments
+///
+/// * `level` - `error` or `warning`
+/// * `kind` - the kind of error or warning
+/// * `file_name` - name of the file the message is about
+/// * `line` - line number, starting at 1
+/// * `column` - column of the offending text, starting at 1
+/// * `text` - the offending text
+/// * `source_line` - the source line
+fn render_message(
+    level: &str,
+    kind: &dyn Display,
+    file_name: &str,
+    line: usize,
+    column: usize,
+    text: &str,
+    source_line: &str) -> String {
+
+    let line_number = line.to_string();
+    let gutter = " ".repeat(line_number.len());
+
+    // keep tabs in the caret prefix so the caret lines up with the source line
+    let prefix: String = source_line.chars()
+        .take(column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let carets = "^".repeat(text.chars().count().max(1));
+
+    format!("{level}: {kind} `{text}`\n\
+        {gutter}--> {file_name}:{line}:{column}\n\
+        {gutter} |\n\
+        {line} | {source_line}\n\
+        {gutter} | {prefix}{carets}\n",
+        level=level, kind=kind, text=text, gutter=gutter, file_name=file_name,
+        line=line_number, column=column, source_line=source_line,
+        prefix=prefix, carets=carets)
+}
+
+#[test]
+fn test_render() {
+    let source = "@1\n\tM=D+D // oops\n";
+    let mut error = crate::assemble(source).unwrap_err();
+    error.file = "test.asm".to_string();
+    let expected = "error: no mapping found for comp mnemonic `D+D`\n \
+        --> test.asm:2:4\n  \
+        |\n\
+        2 | \tM=D+D // oops\n  \
+        | \t  ^^^\n";
+    assert_eq!(expected, render(&error));
+}
+
+#[test]
+fn test_render_warning() {
+    let program = crate::assemble("(END)\n@1\n").unwrap();
+    let expected = "warning: label is never referenced `END`\n \
+        --> :1:2\n  \
+        |\n\
+        1 | (END)\n  \
+        |  ^^^\n";
+    assert_eq!(expected, render_warning(&program.warnings[0]));
+}
@@ -0,0 +1,240 @@
+// Disassembler (Machine Code -> Mnemonics) for the Nand2Tetris Hack Computer
+// Author: Leo Robinovitch
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::{comp_map, dest_map, jump_map, shift_comp_map};
+
+/// The kind of problem found while disassembling a line
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum DisassembleErrorKind {
+    InvalidWord,
+    UnknownComp,
+}
+
+impl fmt::Display for DisassembleErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            DisassembleErrorKind::InvalidWord => "expected 16 binary digits",
+            DisassembleErrorKind::UnknownComp => "no comp mnemonic for bits",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+
+/// Error returned by `disassemble`
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct DisassembleError {
+    pub kind: DisassembleErrorKind,
+    /// line number in the .hack file, starting at 1
+    pub line: usize,
+    /// the offending text
+    pub text: String,
+}
+
+impl fmt::Display for DisassembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} `{}`", self.line, self.kind, self.text)
+    }
+}
+
+impl Error for DisassembleError {}
+
+
+/// Returns `map` with keys and values swapped
+///
+/// # Arguments
+///
+/// * `map` - hashmap of mnemonics to bits
+fn invert(map: HashMap<&'static str, u16>) -> HashMap<u16, &'static str> {
+    map.into_iter().map(|(mnemonic, bits)| (bits, mnemonic)).collect()
+}
+
+#[test]
+fn test_invert() {
+    let inverted = invert(jump_map());
+    assert_eq!(Some(&"JMP"), inverted.get(&0b111));
+    assert_eq!(Some(&""), inverted.get(&0b000));
+}
+
+
+/// Parses the words of a .hack file, one line of 16 binary digits per word
+///
+/// # Arguments
+///
+/// * `hack` - contents of a .hack file
+fn parse_words(hack: &str) -> Result<Vec<u16>, DisassembleError> {
+    let mut words: Vec<u16> = Vec::new();
+    for (idx, line) in hack.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() { continue };
+
+        if line.len() != 16 || !line.chars().all(|c| c == '0' || c == '1') {
+            return Err(DisassembleError {
+                kind: DisassembleErrorKind::InvalidWord,
+                line: idx + 1,
+                text: line.to_string(),
+            });
+        }
+        words.push(u16::from_str_radix(line, 2).unwrap());
+    }
+    Ok(words)
+}
+
+#[test]
+fn test_parse_words() {
+    assert_eq!(Ok(vec![2, 0b1110110000010000]), parse_words("0000000000000010\n\n1110110000010000\n"));
+    let error = parse_words("0000000000000010\n000000000000001\n").unwrap_err();
+    assert_eq!((DisassembleErrorKind::InvalidWord, 2), (error.kind, error.line));
+}
+
+
+/// Checks if the word is a C command with a jump
+///
+/// # Arguments
+///
+/// * `word` - machine code word
+fn is_jump(word: u16) -> bool {
+    word & 0x8000 != 0 && word & 0b111 != 0
+}
+
+
+/// Returns the name of the made up label for a jump target
+///
+/// # Arguments
+///
+/// * `address` - ROM address of the jump target
+fn label_name(address: u16) -> String {
+    format!("L_{:04}", address)
+}
+
+
+/// Disassembles the contents of a .hack file to Hack assembly. Every
+/// A command that feeds a jump gets a made up label, e.g. `L_0042`, so the
+/// output reassembles to the same words.
+///
+/// # Arguments
+///
+/// * `hack` - contents of a .hack file
+pub fn disassemble(hack: &str) -> Result<String, DisassembleError> {
+    let comp_bits_map = invert(comp_map());
+    let shift_comp_bits_map = invert(shift_comp_map());
+    let dest_bits_map = invert(dest_map());
+    let jump_bits_map = invert(jump_map());
+
+    let words = parse_words(hack)?;
+
+    // line numbers of each word, for errors
+    let line_numbers: Vec<usize> = hack.lines().enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, _)| idx + 1)
+        .collect();
+
+    // first pass: find A commands that load a jump target
+    let mut labels: BTreeSet<u16> = BTreeSet::new();
+    for pair in words.windows(2) {
+        let (a_word, c_word) = (pair[0], pair[1]);
+        let feeds_jump = a_word & 0x8000 == 0 && is_jump(c_word);
+        if feeds_jump && (a_word as usize) <= words.len() {
+            labels.insert(a_word);
+        }
+    }
+
+    // second pass: translate each word to a mnemonic
+    let mut asm = String::new();
+    for (address, word) in words.iter().enumerate() {
+        let is_jump_target = labels.contains(&(address as u16));
+        if is_jump_target {
+            asm.push_str(&format!("({})\n", label_name(address as u16)));
+        }
+
+        if word & 0x8000 == 0 {
+            let next_jumps = words.get(address + 1).is_some_and(|next| is_jump(*next));
+            if next_jumps && labels.contains(word) {
+                asm.push_str(&format!("@{}\n", label_name(*word)));
+            } else {
+                asm.push_str(&format!("@{}\n", word));
+            }
+            continue;
+        }
+
+        let comp = match word >> 13 {
+            0b111 => comp_bits_map.get(&(word >> 6 & 0x7f)),
+            0b101 => shift_comp_bits_map.get(&(word >> 6 & 0x7f)),
+            _ => None
+        };
+        let dest = dest_bits_map.get(&(word >> 3 & 0b111)).unwrap();
+        let jump = jump_bits_map.get(&(word & 0b111)).unwrap();
+        let comp = match comp {
+            Some(comp) => comp,
+            None => {
+                return Err(DisassembleError {
+                    kind: DisassembleErrorKind::UnknownComp,
+                    line: line_numbers[address],
+                    text: format!("{:016b}", word),
+                });
+            }
+        };
+
+        let mut command = String::new();
+        if !dest.is_empty() {
+            command.push_str(dest);
+            command.push('=');
+        }
+        command.push_str(comp);
+        if !jump.is_empty() {
+            command.push(';');
+            command.push_str(jump);
+        }
+        asm.push_str(&command);
+        asm.push('\n');
+    }
+
+    // a jump past the last instruction, e.g. to an end label
+    if labels.contains(&(words.len() as u16)) {
+        asm.push_str(&format!("({})\n", label_name(words.len() as u16)));
+    }
+
+    Ok(asm)
+}
+
+#[test]
+fn test_disassemble() {
+    let hack = "0000000000000010\n\
+        1110110000010000\n\
+        0000000000000000\n\
+        1110001100001000\n\
+        0000000000000100\n\
+        1110101010000111\n";
+    let expected = "@2\n\
+        D=A\n\
+        @0\n\
+        M=D\n\
+        (L_0004)\n\
+        @L_0004\n\
+        0;JMP\n";
+    assert_eq!(Ok(expected.to_string()), disassemble(hack));
+
+    let error = disassemble("0000000000000010\n1100110000010000\n").unwrap_err();
+    assert_eq!((DisassembleErrorKind::UnknownComp, 2), (error.kind, error.line));
+
+    let shift = disassemble("1010110000010000\n");
+    if cfg!(feature = "extended-alu") {
+        assert_eq!(Ok("D=D<<\n".to_string()), shift);
+    } else {
+        assert!(shift.is_err());
+    }
+}
+
+#[test]
+fn test_disassemble_reassembles() {
+    let source = include_str!("../../rect/Rect.asm");
+    let hack = crate::assemble(source).unwrap().to_hack().join("\n");
+    let asm = disassemble(&hack).unwrap();
+    assert!(asm.contains("(L_0010)"));
+    assert_eq!(hack, crate::assemble(&asm).unwrap().to_hack().join("\n"));
+}
@@ -0,0 +1,289 @@
+// Constant expressions in A commands and .equ directives, e.g. `@SCREEN+32*5`
+// Author: Leo Robinovitch
+//
+// expr    = term (('+' | '-') term)*
+// term    = unary (('*' | '/') unary)*
+// unary   = '-' unary | primary
+// primary = number | symbol | '(' expr ')'
+// number  = decimal | '0x' hex | '0b' binary | "'" char "'"
+
+use crate::{ErrorKind, LineError};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Token<'a> {
+    Number(&'a str),
+    Symbol(&'a str),
+    Op(&'a str),
+}
+
+impl<'a> Token<'a> {
+    /// Returns the text of the token
+    fn text(&self) -> &'a str {
+        match self {
+            Token::Number(text) | Token::Symbol(text) | Token::Op(text) => text
+        }
+    }
+}
+
+
+/// Returns the value of a numeric literal: decimal `16`, hex `0x4000`,
+/// binary `0b1010` or character `'A'`
+///
+/// # Arguments
+///
+/// * `text` - the literal
+pub fn parse_number(text: &str) -> Option<i32> {
+    let mut chars = text.chars();
+    if let (Some('\''), Some(c), Some('\''), None) = (chars.next(), chars.next(), chars.next(), chars.next()) {
+        return if c == '\'' { None } else { Some(c as i32) };
+    }
+
+    let (digits, radix) = if text.starts_with("0x") || text.starts_with("0X") {
+        (&text[2..], 16)
+    } else if text.starts_with("0b") || text.starts_with("0B") {
+        (&text[2..], 2)
+    } else {
+        (text, 10)
+    };
+    // from_str_radix also accepts a leading sign, which is not part of a literal
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    i32::from_str_radix(digits, radix).ok()
+}
+
+#[test]
+fn test_parse_number() {
+    assert_eq!(Some(16), parse_number("16"));
+    assert_eq!(Some(16384), parse_number("0x4000"));
+    assert_eq!(Some(255), parse_number("0XfF"));
+    assert_eq!(Some(10), parse_number("0b1010"));
+    assert_eq!(Some(65), parse_number("'A'"));
+    assert_eq!(Some(32), parse_number("' '"));
+    assert_eq!(None, parse_number("0x"));
+    assert_eq!(None, parse_number("0b102"));
+    assert_eq!(None, parse_number("+5"));
+    assert_eq!(None, parse_number("1x"));
+    assert_eq!(None, parse_number("'AB'"));
+    assert_eq!(None, parse_number("99999999999"));
+}
+
+
+/// Splits an expression into tokens
+///
+/// # Arguments
+///
+/// * `expr` - the expression
+fn tokenize(expr: &str) -> Result<Vec<Token<'_>>, LineError> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut chars = expr.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut end = start + c.len_utf8();
+        if c.is_whitespace() {
+            continue;
+        } else if c == '\'' {
+            // character literal, which may hold any character, e.g. '+'
+            match (chars.next(), chars.next()) {
+                (Some((_, quoted)), Some((idx, '\''))) if quoted != '\'' => {
+                    end = idx + 1;
+                    tokens.push(Token::Number(&expr[start..end]));
+                },
+                _ => return Err(LineError::new(ErrorKind::InvalidExpression, expr, &expr[start..end]))
+            }
+        } else if "+-*/()".contains(c) {
+            tokens.push(Token::Op(&expr[start..end]));
+        } else if c.is_ascii_alphanumeric() || "_.$:".contains(c) {
+            while let Some(&(idx, next)) = chars.peek() {
+                if !(next.is_ascii_alphanumeric() || "_.$:".contains(next)) { break };
+                end = idx + next.len_utf8();
+                chars.next();
+            }
+            let text = &expr[start..end];
+            if c.is_ascii_digit() {
+                tokens.push(Token::Number(text));
+            } else {
+                tokens.push(Token::Symbol(text));
+            }
+        } else {
+            return Err(LineError::new(ErrorKind::InvalidExpression, expr, &expr[start..end]));
+        }
+    }
+    Ok(tokens)
+}
+
+#[test]
+fn test_tokenize() {
+    let tokens = tokenize("SCREEN + 32*(5-x)").unwrap();
+    let texts: Vec<&str> = tokens.iter().map(|token| token.text()).collect();
+    assert_eq!(vec!["SCREEN", "+", "32", "*", "(", "5", "-", "x", ")"], texts);
+    assert_eq!(Token::Number("32"), tokens[2]);
+    assert!(tokenize("1 % 2").is_err());
+    let tokens = tokenize("'+'+0x10").unwrap();
+    assert_eq!(vec![Token::Number("'+'"), Token::Op("+"), Token::Number("0x10")], tokens);
+    assert!(tokenize("'A").is_err());
+}
+
+
+/// Recursive descent evaluator over the tokens of one expression
+struct Evaluator<'a, 'b> {
+    expr: &'a str,
+    tokens: Vec<Token<'a>>,
+    position: usize,
+    lookup: &'b dyn Fn(&str) -> Option<i32>,
+}
+
+impl<'a, 'b> Evaluator<'a, 'b> {
+    /// Returns the next token without consuming it
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.position).copied()
+    }
+
+    /// Returns an error at the current token, or at the end of the expression
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - the kind of error
+    fn error(&self, kind: ErrorKind) -> LineError {
+        match self.peek() {
+            Some(token) => LineError::new(kind, self.expr, token.text()),
+            None => LineError::new(kind, self.expr, &self.expr[self.expr.len()..])
+        }
+    }
+
+    /// Evaluates a sum or difference of terms
+    fn expr(&mut self) -> Result<i32, LineError> {
+        let mut value = self.term()?;
+        while let Some(Token::Op(op)) = self.peek() {
+            if op != "+" && op != "-" { break };
+            self.position += 1;
+            let rhs = self.term()?;
+            value = if op == "+" { value.wrapping_add(rhs) } else { value.wrapping_sub(rhs) };
+        }
+        Ok(value)
+    }
+
+    /// Evaluates a product or quotient of unary expressions
+    fn term(&mut self) -> Result<i32, LineError> {
+        let mut value = self.unary()?;
+        while let Some(Token::Op(op)) = self.peek() {
+            if op != "*" && op != "/" { break };
+            self.position += 1;
+            let divisor_error = self.error(ErrorKind::DivisionByZero);
+            let rhs = self.unary()?;
+            value = if op == "*" {
+                value.wrapping_mul(rhs)
+            } else if rhs == 0 {
+                return Err(divisor_error);
+            } else {
+                value.wrapping_div(rhs)
+            };
+        }
+        Ok(value)
+    }
+
+    /// Evaluates a negated or plain primary expression
+    fn unary(&mut self) -> Result<i32, LineError> {
+        if let Some(Token::Op("-")) = self.peek() {
+            self.position += 1;
+            return Ok(self.unary()?.wrapping_neg());
+        }
+        self.primary()
+    }
+
+    /// Evaluates a number, symbol or parenthesized expression
+    fn primary(&mut self) -> Result<i32, LineError> {
+        let token = match self.peek() {
+            Some(token) => token,
+            None => return Err(self.error(ErrorKind::InvalidExpression))
+        };
+        match token {
+            Token::Number(text) => {
+                let value = parse_number(text)
+                    .ok_or_else(|| LineError::new(ErrorKind::InvalidNumber, self.expr, text))?;
+                self.position += 1;
+                Ok(value)
+            },
+            Token::Symbol(text) => {
+                let value = (self.lookup)(text)
+                    .ok_or_else(|| LineError::new(ErrorKind::UndefinedSymbol, self.expr, text))?;
+                self.position += 1;
+                Ok(value)
+            },
+            Token::Op("(") => {
+                self.position += 1;
+                let value = self.expr()?;
+                match self.peek() {
+                    Some(Token::Op(")")) => {
+                        self.position += 1;
+                        Ok(value)
+                    },
+                    _ => Err(self.error(ErrorKind::InvalidExpression))
+                }
+            },
+            Token::Op(_) => Err(self.error(ErrorKind::InvalidExpression))
+        }
+    }
+}
+
+
+/// Returns the symbols used in an expression, in order, or none if the
+/// expression is invalid
+///
+/// # Arguments
+///
+/// * `expr` - the expression
+pub fn symbols(expr: &str) -> Vec<&str> {
+    tokenize(expr).unwrap_or_default().into_iter()
+        .filter_map(|token| match token {
+            Token::Symbol(text) => Some(text),
+            _ => None
+        })
+        .collect()
+}
+
+#[test]
+fn test_symbols() {
+    assert_eq!(vec!["SCREEN", "ROW"], symbols("SCREEN + 32*ROW"));
+    assert!(symbols("1 % 2").is_empty());
+}
+
+
+/// Evaluates a constant expression. Errors are located relative to `expr`.
+///
+/// # Arguments
+///
+/// * `expr` - the expression, e.g. `SCREEN+32*5`
+/// * `lookup` - returns the value of a symbol, if it is defined
+pub fn evaluate(expr: &str, lookup: &dyn Fn(&str) -> Option<i32>) -> Result<i32, LineError> {
+    let tokens = tokenize(expr)?;
+    let mut evaluator = Evaluator { expr, tokens, position: 0, lookup };
+    let value = evaluator.expr()?;
+    if evaluator.peek().is_some() {
+        return Err(evaluator.error(ErrorKind::InvalidExpression));
+    }
+    Ok(value)
+}
+
+#[test]
+fn test_evaluate() {
+    let lookup = |symbol: &str| match symbol {
+        "SCREEN" => Some(16384),
+        "LOOP" => Some(10),
+        _ => None
+    };
+    assert_eq!(Ok(16544), evaluate("SCREEN+32*5", &lookup));
+    assert_eq!(Ok(9), evaluate("LOOP-1", &lookup));
+    assert_eq!(Ok(-4), evaluate("-(2+2)", &lookup));
+    assert_eq!(Ok(3), evaluate("7 / 2", &lookup));
+    assert_eq!(Ok(1), evaluate("10-3*3", &lookup));
+    assert_eq!(Ok(16384 + 'a' as i32), evaluate("0x4000 + 'a'", &lookup));
+
+    let error = evaluate("SCREEN+x", &lookup).unwrap_err();
+    assert_eq!((ErrorKind::UndefinedSymbol, 7, "x"), (error.kind, error.offset, error.text.as_str()));
+    let error = evaluate("1/(2-2)", &lookup).unwrap_err();
+    assert_eq!((ErrorKind::DivisionByZero, 2), (error.kind, error.offset));
+    let error = evaluate("(1+2", &lookup).unwrap_err();
+    assert_eq!((ErrorKind::InvalidExpression, 4), (error.kind, error.offset));
+    let error = evaluate("1 2", &lookup).unwrap_err();
+    assert_eq!((ErrorKind::InvalidExpression, 2), (error.kind, error.offset));
+}
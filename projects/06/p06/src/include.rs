@@ -0,0 +1,153 @@
+// Reading .asm sources and their .include "other.asm" directives
+// Author: Leo Robinovitch
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use regex::Regex;
+
+use crate::{remove_comments, AssembleError, ErrorKind, LineError, Source, SourceLine};
+
+/// Deepest allowed nesting of included files
+const MAX_DEPTH: usize = 32;
+
+/// Get the path of an include directive, e.g. `.include "other.asm"`
+///
+/// # Arguments
+///
+/// * `command` - a string slice that holds the include directive
+fn get_include_path(command: &str) -> Result<&str, LineError> {
+    lazy_static! { // lazy_static ensures compilation only happens once
+        static ref RE : Regex = Regex::new(
+                r#"^\.include\s+"([^"]+)"$"#
+            ).unwrap();
+    };
+
+    match RE.captures(command) {
+        Some(capture) => Ok(capture.get(1).unwrap().as_str()),
+        None => Err(LineError::new(ErrorKind::InvalidInclude, command, command))
+    }
+}
+
+#[test]
+fn test_get_include_path() {
+    assert_eq!(Ok("lib/mult.asm"), get_include_path(".include \"lib/mult.asm\""));
+    assert!(get_include_path(".include lib/mult.asm").is_err());
+    assert!(get_include_path(".include \"a.asm\" x").is_err());
+}
+
+
+/// Pushes the lines of a file to `lines`, replacing include directives
+/// with the lines of the included file
+///
+/// # Arguments
+///
+/// * `name` - name of the file, used to find included files and in errors
+/// * `contents` - contents of the file
+/// * `load` - reads the contents of an included file
+/// * `stack` - names of the files currently being included, to catch cycles
+/// * `lines` - lines are pushed here
+/// * `errors` - errors found are pushed here
+fn read_file(
+    name: &str,
+    contents: &str,
+    load: &dyn Fn(&Path) -> io::Result<String>,
+    stack: &mut Vec<PathBuf>,
+    lines: &mut Vec<SourceLine>,
+    errors: &mut Vec<AssembleError>) {
+
+    let file: Rc<str> = Rc::from(name);
+    let dir = Path::new(name).parent().unwrap_or_else(|| Path::new(""));
+    stack.push(PathBuf::from(name));
+
+    for (idx, text) in contents.lines().enumerate() {
+        let line = SourceLine { file: file.clone(), number: idx + 1, text: Rc::from(text) };
+        if !text.trim_start().starts_with(".include") {
+            lines.push(line);
+            continue;
+        }
+        let stripped_line = remove_comments(&line.text);
+
+        let include_path = match get_include_path(stripped_line) {
+            Ok(include_path) => include_path,
+            Err(e) => {
+                errors.push(e.locate(&line, stripped_line));
+                continue;
+            }
+        };
+        let path = dir.join(include_path);
+        if stack.contains(&path) || stack.len() >= MAX_DEPTH {
+            let error = LineError::new(ErrorKind::RecursiveInclude, stripped_line, include_path);
+            errors.push(error.locate(&line, stripped_line));
+            continue;
+        }
+        match load(&path) {
+            Ok(included) => {
+                read_file(&path.to_string_lossy(), &included, load, stack, lines, errors);
+            },
+            Err(_) => {
+                let error = LineError::new(ErrorKind::IncludeNotFound, stripped_line, include_path);
+                errors.push(error.locate(&line, stripped_line));
+            }
+        }
+    }
+
+    stack.pop();
+}
+
+
+/// Returns the lines of the sources, in order, with include directives
+/// replaced by the lines of the included files. Included paths are
+/// relative to the including file.
+///
+/// # Arguments
+///
+/// * `sources` - named .asm sources
+/// * `load` - reads the contents of an included file
+/// * `errors` - errors found are pushed here
+pub fn read_sources(
+    sources: &[Source],
+    load: &dyn Fn(&Path) -> io::Result<String>,
+    errors: &mut Vec<AssembleError>) -> Vec<SourceLine> {
+
+    let mut lines: Vec<SourceLine> = Vec::new();
+    for source in sources {
+        read_file(&source.name, &source.contents, load, &mut Vec::new(), &mut lines, errors);
+    }
+    lines
+}
+
+#[test]
+fn test_read_sources() {
+    let load = |path: &Path| match path.to_str().unwrap() {
+        "lib/mult.asm" => Ok(".include \"zero.asm\"\n@mult".to_string()),
+        "lib/zero.asm" => Ok("@zero".to_string()),
+        "lib/loop.asm" => Ok(".include \"loop.asm\"".to_string()),
+        _ => Err(io::Error::new(io::ErrorKind::NotFound, "not found"))
+    };
+    let sources = vec![
+        Source { name: "main.asm".to_string(), contents: "@1\n.include \"lib/mult.asm\"\n@2".to_string() },
+        Source { name: "other.asm".to_string(), contents: ".include \"lib/loop.asm\"\n.include \"x.asm\"".to_string() },
+    ];
+    let mut errors: Vec<AssembleError> = Vec::new();
+    let lines = read_sources(&sources, &load, &mut errors);
+
+    let found: Vec<(&str, usize, &str)> = lines.iter()
+        .map(|line| (&*line.file, line.number, &*line.text))
+        .collect();
+    assert_eq!(vec![
+        ("main.asm", 1, "@1"),
+        ("lib/zero.asm", 1, "@zero"),
+        ("lib/mult.asm", 2, "@mult"),
+        ("main.asm", 3, "@2"),
+    ], found);
+
+    let found: Vec<(ErrorKind, &str, usize)> = errors.iter()
+        .map(|e| (e.kind, e.file.as_str(), e.line))
+        .collect();
+    assert_eq!(vec![
+        (ErrorKind::RecursiveInclude, "lib/loop.asm", 1),
+        (ErrorKind::IncludeNotFound, "other.asm", 2),
+    ], found);
+}
@@ -0,0 +1,1244 @@
+// Assembler (Mnemonics -> Machine Code) for the Nand2Tetris Hack Computer
+// Author: Leo Robinovitch
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use regex::Regex;
+
+use parser::{Instruction, Operand};
+#[macro_use]
+extern crate lazy_static;
+
+mod data;
+pub mod diagnostic;
+pub mod disassemble;
+mod expr;
+mod include;
+pub mod lint;
+pub mod link;
+pub mod listing;
+mod local;
+mod macros;
+pub mod object;
+mod optimize;
+pub mod output;
+pub mod parser;
+
+/// Largest value an A command can load, since bit 15 of an A command is 0
+pub const MAX_A_VALUE: i32 = 32767;
+
+
+/// Number of words in ROM
+pub const ROM_SIZE: usize = 32768;
+
+
+/// RAM address of the first variable
+pub const VARIABLE_START: u16 = 16;
+
+
+/// RAM address of the stack, the first address past the variables that
+/// the VM translator leaves room for
+pub const STACK_START: u16 = 256;
+
+
+/// RAM address of the screen memory map, which variables must stay below
+pub const SCREEN_START: u16 = 16384;
+
+
+/// Symbols starting with this prefix are private to the file they are in
+pub const LOCAL_PREFIX: char = '_';
+
+
+/// A named .asm source
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Source {
+    /// file name, used to find included files and in errors
+    pub name: String,
+    pub contents: String,
+}
+
+
+/// A line of source after includes and macro expansion
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct SourceLine {
+    /// name of the file the line came from
+    pub file: Rc<str>,
+    /// line number in the file, starting at 1. Lines expanded from a
+    /// macro have the line number of the invocation.
+    pub number: usize,
+    /// text of the line, shared by the instructions and program lines it
+    /// is parsed into
+    pub text: Rc<str>,
+}
+
+
+/// Options for `assemble_sources`
+#[derive(PartialEq, Eq, Debug, Default, Clone)]
+pub struct Options {
+    /// rewrite instruction sequences to fewer instructions before encoding
+    pub optimize: bool,
+}
+
+
+/// Assembled machine code, one 16 bit word per ROM address
+#[derive(PartialEq, Eq, Debug, Default)]
+pub struct Program {
+    pub words: Vec<u16>,
+    /// source line of each word
+    pub lines: Vec<SourceLine>,
+    /// labels from the first pass and their ROM addresses, in source order
+    pub labels: Vec<(String, u16)>,
+    /// variables and their allocated RAM addresses, in allocation order
+    pub variables: Vec<(String, u16)>,
+    /// likely mistakes found by the lint pass
+    pub warnings: Vec<lint::Warning>,
+    /// RAM ranges set by data directives, as first address and word count
+    pub data: Vec<(u16, u16)>,
+}
+
+impl Program {
+    /// Returns the 16 character text representation of each word,
+    /// i.e. the lines of a .hack file
+    pub fn to_hack(&self) -> Vec<String> {
+        self.words.iter().map(|word| format!("{:016b}", word)).collect()
+    }
+}
+
+
+/// The kind of problem found while assembling a line
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ErrorKind {
+    InvalidSymbol,
+    InvalidACommand,
+    InvalidLCommand,
+    InvalidCCommand,
+    InvalidNumber,
+    UnknownComp,
+    UnknownDest,
+    UnknownJump,
+    InvalidMacro,
+    NestedMacro,
+    DuplicateMacro,
+    UnmatchedEndm,
+    UnterminatedMacro,
+    MacroArguments,
+    RecursiveMacro,
+    InvalidEqu,
+    InvalidExpression,
+    DivisionByZero,
+    UndefinedSymbol,
+    DuplicateSymbol,
+    InvalidInclude,
+    IncludeNotFound,
+    RecursiveInclude,
+    NumberOutOfRange,
+    RomFull,
+    RamFull,
+    RelocatableExpression,
+    InvalidData,
+    DataWithoutAddress,
+    DataOutOfRange,
+    DataInObject,
+    LocalWithoutGlobal,
+    UndefinedLabel,
+    FixedAddress,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            ErrorKind::InvalidSymbol => "invalid symbol",
+            ErrorKind::InvalidACommand => "invalid A command",
+            ErrorKind::InvalidLCommand => "invalid L command",
+            ErrorKind::InvalidCCommand => "invalid C command",
+            ErrorKind::InvalidNumber => "invalid number",
+            ErrorKind::UnknownComp => "no mapping found for comp mnemonic",
+            ErrorKind::UnknownDest => "no mapping found for dest mnemonic",
+            ErrorKind::UnknownJump => "no mapping found for jump mnemonic",
+            ErrorKind::InvalidMacro => "invalid macro definition",
+            ErrorKind::NestedMacro => "macro definition inside macro definition",
+            ErrorKind::DuplicateMacro => "macro defined more than once",
+            ErrorKind::UnmatchedEndm => ".endm without .macro",
+            ErrorKind::UnterminatedMacro => ".macro without .endm",
+            ErrorKind::MacroArguments => "wrong number of macro arguments",
+            ErrorKind::RecursiveMacro => "macro expansion too deep",
+            ErrorKind::InvalidEqu => "invalid .equ directive",
+            ErrorKind::InvalidExpression => "invalid expression at",
+            ErrorKind::DivisionByZero => "division by zero at",
+            ErrorKind::UndefinedSymbol => "undefined symbol in expression",
+            ErrorKind::DuplicateSymbol => "symbol already defined",
+            ErrorKind::InvalidInclude => "invalid .include directive",
+            ErrorKind::IncludeNotFound => "could not read included file",
+            ErrorKind::RecursiveInclude => "file includes itself",
+            ErrorKind::NumberOutOfRange => "A command value must be between 0 and 32767, found",
+            ErrorKind::RomFull => "program does not fit in the 32768 words of ROM at",
+            ErrorKind::RamFull => "no RAM left for variable below the screen at 16384",
+            ErrorKind::RelocatableExpression => "expressions in object files cannot use labels, found",
+            ErrorKind::InvalidData => "invalid data directive",
+            ErrorKind::DataWithoutAddress => "data directive without a .data address before it",
+            ErrorKind::DataOutOfRange => "data word must be between -32768 and 65535, found",
+            ErrorKind::DataInObject => "data directives cannot be used in object files",
+            ErrorKind::LocalWithoutGlobal => "local label without a global label before it, found",
+            ErrorKind::UndefinedLabel => "no label found for local or anonymous label",
+            ErrorKind::FixedAddress => "cannot optimize a program that computes or hard-codes a ROM address, found",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+
+/// Error returned by `assemble`, located in the source
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct AssembleError {
+    pub kind: ErrorKind,
+    /// name of the file the error is in
+    pub file: String,
+    /// line number in the file, starting at 1
+    pub line: usize,
+    /// column of the offending text in the line, starting at 1
+    pub column: usize,
+    /// the offending text
+    pub text: String,
+    /// the source line, after macro expansion
+    pub source: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.file.is_empty() {
+            write!(f, "{}:", self.file)?;
+        }
+        write!(f, "{}:{}: {} `{}`", self.line, self.column, self.kind, self.text)
+    }
+}
+
+impl Error for AssembleError {}
+
+
+/// An error within a single stripped line, before its line number is known
+#[derive(PartialEq, Eq, Debug)]
+struct LineError {
+    kind: ErrorKind,
+    /// byte offset of the offending text in the stripped line
+    offset: usize,
+    text: String,
+}
+
+impl LineError {
+    /// Returns a LineError for `text`, which must be a slice of `line`
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - the kind of error
+    /// * `line` - the stripped line being parsed
+    /// * `text` - the offending slice of `line`
+    fn new(kind: ErrorKind, line: &str, text: &str) -> LineError {
+        LineError {
+            kind,
+            offset: text.as_ptr() as usize - line.as_ptr() as usize,
+            text: text.to_string(),
+        }
+    }
+
+    /// Moves the error from `part` to the line `part` is a slice of
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - the stripped line being parsed
+    /// * `part` - the slice of `line` the error was found in
+    fn within(mut self, line: &str, part: &str) -> LineError {
+        self.offset += part.as_ptr() as usize - line.as_ptr() as usize;
+        self
+    }
+
+    /// Locates the error in the source line
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - the source line, including comments and whitespace
+    /// * `stripped_line` - the slice of the line's text that was parsed
+    fn locate(self, line: &SourceLine, stripped_line: &str) -> AssembleError {
+        let start = stripped_line.as_ptr() as usize - line.text.as_ptr() as usize + self.offset;
+        AssembleError {
+            kind: self.kind,
+            file: line.file.to_string(),
+            line: line.number,
+            column: line.text[..start].chars().count() + 1,
+            text: self.text,
+            source: line.text.to_string(),
+        }
+    }
+}
+
+
+/// Returns a str after removing comments and white space
+///
+/// # Arguments
+///
+/// * `line` - the current line
+fn remove_comments(line: &str) -> &str {
+
+    // find the index where comments begin on the line, scanning bytes
+    // since searching for a two character pattern is slow on short lines
+    let bytes = line.as_bytes();
+    let idx_comment = (1..bytes.len())
+        .find(|idx| bytes[idx - 1] == b'/' && bytes[*idx] == b'/')
+        .map_or(line.len(), |idx| idx - 1);
+
+    // return a reference to the reduced str with no start/end whitespace
+    // note that memory contents are the same, just pointer and/or len changed
+    line[0..idx_comment].trim()
+}
+
+#[test]
+fn test_stripped_line() {
+    assert_eq!("", remove_comments(""));
+    assert_eq!("", remove_comments("    "));
+    assert_eq!("", remove_comments("//   "));
+    assert_eq!("nand2tetris", remove_comments("nand2tetris   // is so cool"));
+    assert_eq!("nand2tetris is so cool", remove_comments("nand2tetris is so cool // eh?"));
+}
+
+
+/// Get (name, expression) of an equ directive, e.g. `.equ NAME value`
+///
+/// # Arguments
+///
+/// * `command` - a string slice that holds the equ directive
+fn get_equ(command: &str) -> Result<(&str, &str), LineError> {
+    lazy_static! { // lazy_static ensures compilation only happens once
+        static ref RE : Regex = Regex::new(
+                r"^\.equ\s+([^\d][a-zA-Z0-9_\.$:]*)\s+(\S.*)$"
+            ).unwrap();
+    };
+
+    let capture = match RE.captures(command) {
+        Some(capture) => capture,
+        None => return Err(LineError::new(ErrorKind::InvalidEqu, command, command))
+    };
+
+    Ok((capture.get(1).unwrap().as_str(), capture.get(2).unwrap().as_str()))
+}
+
+#[test]
+fn test_get_equ() {
+    assert_eq!(Ok(("ROWS", "256")), get_equ(".equ ROWS 256"));
+    assert_eq!(Ok(("ROW_5", "SCREEN + 32*5")), get_equ(".equ ROW_5 SCREEN + 32*5"));
+    assert!(get_equ(".equ ROWS").is_err());
+    assert!(get_equ(".equ 5 5").is_err());
+}
+
+
+/// Get 16 bit representation of a value loaded by an A command, which
+/// must fit in 15 bits
+///
+/// # Arguments
+///
+/// * `value` - the value to load
+/// * `command` - a string slice that holds the A command
+/// * `text` - the slice of `command` the value came from
+fn check_a_value(value: i32, command: &str, text: &str) -> Result<u16, LineError> {
+    if !(0..=MAX_A_VALUE).contains(&value) {
+        return Err(LineError::new(ErrorKind::NumberOutOfRange, command, text));
+    }
+    Ok(value as u16)
+}
+
+#[test]
+fn test_check_a_value() {
+    assert_eq!(Ok(32767), check_a_value(32767, "@32767", "32767"));
+    assert!(check_a_value(32768, "@32768", "32768").is_err());
+    assert!(check_a_value(-1, "@-1", "-1").is_err());
+}
+
+
+/// Get 16 bit representation of a parsed C command
+///
+/// # Arguments
+///
+/// * `dest` - dest mnemonic
+/// * `comp` - comp mnemonic
+/// * `jump` - jump mnemonic
+/// * `comp_map` - hashmap of comp mnemonics to bits
+/// * `shift_comp_map` - hashmap of shift comp mnemonics to bits
+/// * `dest_map` - hashmap of dest mnemonics to bits
+/// * `jump_map` - hashmap of jump mnemonics to bits
+fn get_c_bits(
+    dest: &str,
+    comp: &str,
+    jump: &str,
+    comp_map: &HashMap<&str, u16>,
+    shift_comp_map: &HashMap<&str, u16>,
+    dest_map: &HashMap<&str, u16>,
+    jump_map: &HashMap<&str, u16>) -> u16 {
+
+    let (prefix, comp_bits) = match shift_comp_map.get(comp) {
+        Some(comp_bits) => (0b101, comp_bits),
+        None => (0b111, &comp_map[comp])
+    };
+    prefix << 13 | comp_bits << 6 | dest_map[dest] << 3 | jump_map[jump]
+}
+
+#[test]
+fn test_get_c_bits() {
+    let (comp_map, shift_comp_map) = (comp_map(), shift_comp_map());
+    let (dest_map, jump_map) = (dest_map(), jump_map());
+    assert_eq!(0b1110001100001000, get_c_bits("M", "D", "", &comp_map, &shift_comp_map, &dest_map, &jump_map));
+    assert_eq!(0b1110101010000111, get_c_bits("", "0", "JMP", &comp_map, &shift_comp_map, &dest_map, &jump_map));
+    #[cfg(feature = "extended-alu")]
+    assert_eq!(0b1010110000010000, get_c_bits("D", "D<<", "", &comp_map, &shift_comp_map, &dest_map, &jump_map));
+}
+
+
+/// Returns hashmap of dest mnemonics to their 3 bits
+pub fn dest_map() -> HashMap<&'static str, u16> {
+    let mut dest_map: HashMap<&str, u16> = HashMap::new();
+    dest_map.insert("",    0b000);
+    dest_map.insert("M",   0b001);
+    dest_map.insert("D",   0b010);
+    dest_map.insert("MD",  0b011);
+    dest_map.insert("A",   0b100);
+    dest_map.insert("AM",  0b101);
+    dest_map.insert("AD",  0b110);
+    dest_map.insert("AMD", 0b111);
+    dest_map
+}
+
+
+/// Returns hashmap of comp mnemonics to their 7 bits, including the a bit
+pub fn comp_map() -> HashMap<&'static str, u16> {
+    let mut comp_map: HashMap<&str, u16> = HashMap::new();
+    comp_map.insert("0",   0b0101010);
+    comp_map.insert("1",   0b0111111);
+    comp_map.insert("-1",  0b0111010);
+    comp_map.insert("D",   0b0001100);
+    comp_map.insert("A",   0b0110000);
+    comp_map.insert("M",   0b1110000);
+    comp_map.insert("!D",  0b0001101);
+    comp_map.insert("!A",  0b0110001);
+    comp_map.insert("!M",  0b1110001);
+    comp_map.insert("-D",  0b0001111);
+    comp_map.insert("-A",  0b0110011);
+    comp_map.insert("-M",  0b1110011);
+    comp_map.insert("D+1", 0b0011111);
+    comp_map.insert("A+1", 0b0110111);
+    comp_map.insert("M+1", 0b1110111);
+    comp_map.insert("D-1", 0b0001110);
+    comp_map.insert("A-1", 0b0110010);
+    comp_map.insert("M-1", 0b1110010);
+    comp_map.insert("D+A", 0b0000010);
+    comp_map.insert("D+M", 0b1000010);
+    comp_map.insert("D-A", 0b0010011);
+    comp_map.insert("D-M", 0b1010011);
+    comp_map.insert("A-D", 0b0000111);
+    comp_map.insert("M-D", 0b1000111);
+    comp_map.insert("D&A", 0b0000000);
+    comp_map.insert("D&M", 0b1000000);
+    comp_map.insert("D|A", 0b0010101);
+    comp_map.insert("D|M", 0b1010101);
+    comp_map
+}
+
+
+/// Returns hashmap of the extended ALU's shift comp mnemonics to bits.
+/// These reuse the comp bits of the standard ALU, so a C command with a
+/// shift comp starts with `101` instead of `111`. Empty unless the
+/// `extended-alu` feature is enabled.
+pub fn shift_comp_map() -> HashMap<&'static str, u16> {
+    #[allow(unused_mut)]
+    let mut shift_comp_map: HashMap<&str, u16> = HashMap::new();
+    #[cfg(feature = "extended-alu")]
+    {
+        shift_comp_map.insert("A<<", 0b0100000);
+        shift_comp_map.insert("D<<", 0b0110000);
+        shift_comp_map.insert("M<<", 0b1100000);
+        shift_comp_map.insert("A>>", 0b0000000);
+        shift_comp_map.insert("D>>", 0b0010000);
+        shift_comp_map.insert("M>>", 0b1000000);
+    }
+    shift_comp_map
+}
+
+
+/// Returns hashmap of jump mnemonics to their 3 bits
+pub fn jump_map() -> HashMap<&'static str, u16> {
+    let mut jump_map: HashMap<&str, u16> = HashMap::new();
+    jump_map.insert("",    0b000);
+    jump_map.insert("JGT", 0b001);
+    jump_map.insert("JEQ", 0b010);
+    jump_map.insert("JGE", 0b011);
+    jump_map.insert("JLT", 0b100);
+    jump_map.insert("JNE", 0b101);
+    jump_map.insert("JLE", 0b110);
+    jump_map.insert("JMP", 0b111);
+    jump_map
+}
+
+
+/// Symbol table from names to values. Names are interned as slices of the
+/// source lines, so only names qualified with a file or global label own
+/// their text.
+pub(crate) type SymbolMap<'a> = HashMap<Cow<'a, str>, i32>;
+
+
+/// Returns initial symbol map of builtins
+fn builtin_symbols() -> SymbolMap<'static> {
+    let mut symbol_map: SymbolMap = HashMap::new();
+    symbol_map.insert(Cow::Borrowed("SP"), 0);
+    symbol_map.insert(Cow::Borrowed("LCL"), 1);
+    symbol_map.insert(Cow::Borrowed("ARG"), 2);
+    symbol_map.insert(Cow::Borrowed("THIS"), 3);
+    symbol_map.insert(Cow::Borrowed("THAT"), 4);
+    symbol_map.insert(Cow::Borrowed("R0"), 0);
+    symbol_map.insert(Cow::Borrowed("R1"), 1);
+    symbol_map.insert(Cow::Borrowed("R2"), 2);
+    symbol_map.insert(Cow::Borrowed("R3"), 3);
+    symbol_map.insert(Cow::Borrowed("R4"), 4);
+    symbol_map.insert(Cow::Borrowed("R5"), 5);
+    symbol_map.insert(Cow::Borrowed("R6"), 6);
+    symbol_map.insert(Cow::Borrowed("R7"), 7);
+    symbol_map.insert(Cow::Borrowed("R8"), 8);
+    symbol_map.insert(Cow::Borrowed("R9"), 9);
+    symbol_map.insert(Cow::Borrowed("R10"), 10);
+    symbol_map.insert(Cow::Borrowed("R11"), 11);
+    symbol_map.insert(Cow::Borrowed("R12"), 12);
+    symbol_map.insert(Cow::Borrowed("R13"), 13);
+    symbol_map.insert(Cow::Borrowed("R14"), 14);
+    symbol_map.insert(Cow::Borrowed("R15"), 15);
+    symbol_map.insert(Cow::Borrowed("SCREEN"), 16384);
+    symbol_map.insert(Cow::Borrowed("KBD"), 24576);
+    symbol_map
+}
+
+
+/// Returns the symbol table key of a symbol, which for symbols starting
+/// with LOCAL_PREFIX includes the file the symbol is used in
+///
+/// # Arguments
+///
+/// * `symbol` - the symbol, or the name of a local or anonymous label
+/// * `file` - name of the file the symbol is used in
+fn qualify<'a>(symbol: impl Into<Cow<'a, str>>, file: &str) -> Cow<'a, str> {
+    let symbol = symbol.into();
+    if symbol.starts_with(LOCAL_PREFIX) {
+        Cow::Owned(format!("{}@{}", symbol, file))
+    } else {
+        symbol
+    }
+}
+
+#[test]
+fn test_qualify() {
+    assert_eq!("LOOP", qualify("LOOP", "mult.asm"));
+    assert_eq!("_loop@mult.asm", qualify("_loop", "mult.asm"));
+}
+
+
+/// Assembles Hack assembly source into machine code, returning the
+/// first error found if the source is invalid
+///
+/// # Arguments
+///
+/// * `file_contents` - contents of a .asm file
+pub fn assemble(file_contents: &str) -> Result<Program, AssembleError> {
+    assemble_all(file_contents).map_err(|mut errors| errors.remove(0))
+}
+
+
+/// Assembles Hack assembly source into machine code, continuing through
+/// both passes after an error so that every error in the source is returned.
+/// Included files are read relative to the current directory.
+///
+/// # Arguments
+///
+/// * `file_contents` - contents of a .asm file
+pub fn assemble_all(file_contents: &str) -> Result<Program, Vec<AssembleError>> {
+    let source = Source { name: String::new(), contents: file_contents.to_string() };
+    assemble_sources(&[source], &|path| fs::read_to_string(path), &Options::default())
+}
+
+
+/// A parsed instruction with its source line and the stripped text it was parsed from
+pub(crate) type ParsedLine<'a> = (&'a SourceLine, &'a str, Instruction<'a>);
+
+
+/// Instructions and equ directives parsed from the source lines
+struct Parsed<'a> {
+    /// instructions as written, for the lint pass
+    instructions: Vec<ParsedLine<'a>>,
+    /// instructions to encode, after the optimizer if enabled
+    program: Vec<ParsedLine<'a>>,
+    equs: Vec<(&'a SourceLine, &'a str)>,
+    data: Vec<(&'a SourceLine, &'a str, data::Directive<'a>)>,
+}
+
+
+/// Returns the lines of the sources with includes read and macros expanded
+///
+/// # Arguments
+///
+/// * `sources` - named .asm sources
+/// * `load` - reads the contents of an included file
+/// * `errors` - errors found are pushed here
+fn read_lines(
+    sources: &[Source],
+    load: &dyn Fn(&Path) -> io::Result<String>,
+    errors: &mut Vec<AssembleError>) -> Vec<SourceLine> {
+
+    let source_lines = include::read_sources(sources, load, errors);
+    macros::expand_macros(source_lines, errors)
+}
+
+
+/// Parses each line into an instruction, equ or data directive
+///
+/// # Arguments
+///
+/// * `source_lines` - lines with includes read and macros expanded
+/// * `options` - assembler options
+/// * `errors` - errors found are pushed here
+fn parse_lines<'a>(source_lines: &'a [SourceLine], options: &Options, errors: &mut Vec<AssembleError>)
+    -> Parsed<'a> {
+
+    let mut instructions: Vec<ParsedLine> = Vec::new();
+    let mut equs: Vec<(&SourceLine, &str)> = Vec::new();
+    let mut data: Vec<(&SourceLine, &str, data::Directive)> = Vec::new();
+    for line in source_lines {
+
+        // strip comments
+        let stripped_line = remove_comments(&line.text);
+        if stripped_line.is_empty() { continue };
+
+        if stripped_line.starts_with(".equ") {
+            equs.push((line, stripped_line)); // equ directives don't count as lines
+            continue;
+        }
+        if data::is_directive(stripped_line) {
+            match data::parse_directive(stripped_line) {
+                Ok(directive) => data.push((line, stripped_line, directive)),
+                Err(e) => errors.push(e.locate(line, stripped_line))
+            }
+            continue;
+        }
+
+        match parser::parse_instruction(stripped_line) {
+            Ok(instruction) => instructions.push((line, stripped_line, instruction)),
+            Err(e) => errors.push(e.locate(line, stripped_line))
+        }
+    }
+
+    let program = if options.optimize {
+        optimize::check_addresses(&instructions, &equs, &data, errors);
+        optimize::optimize(instructions.clone())
+    } else {
+        instructions.clone()
+    };
+    Parsed { instructions, program, equs, data }
+}
+
+
+/// Adds the ROM address of each label to the symbol map and to `labels`,
+/// returning the address after the last instruction
+///
+/// # Arguments
+///
+/// * `program` - instructions to encode
+/// * `start` - ROM address of the first instruction
+/// * `symbol_map` - symbol table
+/// * `labels` - labels and their addresses are pushed here, in source
+///   order. Anonymous labels are left out, having no name of their own.
+/// * `errors` - errors found are pushed here
+fn define_labels<'a>(
+    program: &[ParsedLine<'a>],
+    start: i32,
+    symbol_map: &mut SymbolMap<'a>,
+    labels: &mut Vec<(String, u16)>,
+    errors: &mut Vec<AssembleError>) -> i32 {
+
+    let mut scope = local::Scope::default();
+    let mut line_count = start;
+    for (line, stripped_line, instruction) in program {
+        if let Instruction::Label(symbol) = instruction {
+            match scope.declare(line, symbol) {
+                Ok(name) => {
+                    let key = qualify(name, &line.file);
+                    if !symbol_map.contains_key(key.as_ref()) { // if symbol not already in table
+                        symbol_map.insert(key.clone(), line_count);
+                        if !local::is_anonymous(symbol) {
+                            labels.push((key.into_owned(), line_count as u16));
+                        }
+                    }
+                },
+                Err(kind) => errors.push(LineError::new(kind, stripped_line, symbol).locate(line, stripped_line))
+            }
+            continue; // don't count label symbol as a line
+        }
+        line_count += 1;
+    }
+    line_count
+}
+
+
+/// Evaluates equ directives in order, adding them to the symbol map
+///
+/// # Arguments
+///
+/// * `equs` - equ directives with their source lines
+/// * `symbol_map` - symbol table
+/// * `errors` - errors found are pushed here
+fn define_constants<'a>(equs: &[(&'a SourceLine, &'a str)], symbol_map: &mut SymbolMap<'a>, errors: &mut Vec<AssembleError>) {
+    for &(line, stripped_line) in equs {
+        let equ = get_equ(stripped_line).and_then(|(name, expr)| {
+            let lookup = |symbol: &str| symbol_map.get(qualify(symbol, &line.file).as_ref()).copied();
+            let value = expr::evaluate(expr, &lookup)
+                .map_err(|e| e.within(stripped_line, expr))?;
+            match symbol_map.entry(qualify(name, &line.file)) {
+                Entry::Vacant(entry) => {
+                    entry.insert(value);
+                    Ok(())
+                },
+                Entry::Occupied(_) => Err(LineError::new(ErrorKind::DuplicateSymbol, stripped_line, name))
+            }
+        });
+        if let Err(e) = equ {
+            errors.push(e.locate(line, stripped_line));
+        }
+    }
+}
+
+
+/// Get 16 bit representation of a parsed C command or an A command with
+/// a known value
+///
+/// # Arguments
+///
+/// * `instruction` - an A command with a number operand or a C command
+/// * `maps` - comp, shift comp, dest and jump maps
+fn get_bits(instruction: &Instruction, maps: &Maps) -> Option<u16> {
+    match instruction {
+        Instruction::A(Operand::Number(value)) => Some(*value),
+        Instruction::C { dest, comp, jump } => {
+            Some(get_c_bits(dest, comp, jump, &maps.comp, &maps.shift_comp, &maps.dest, &maps.jump))
+        },
+        _ => None
+    }
+}
+
+
+/// Mnemonic to bits maps used for encoding
+struct Maps {
+    comp: HashMap<&'static str, u16>,
+    shift_comp: HashMap<&'static str, u16>,
+    dest: HashMap<&'static str, u16>,
+    jump: HashMap<&'static str, u16>,
+}
+
+impl Maps {
+    fn new() -> Maps {
+        Maps { comp: comp_map(), shift_comp: shift_comp_map(), dest: dest_map(), jump: jump_map() }
+    }
+}
+
+
+/// Assembles several Hack assembly sources into one program, in order,
+/// returning every error found. Symbols starting with LOCAL_PREFIX are
+/// private to their file, all others are shared between files.
+///
+/// # Arguments
+///
+/// * `sources` - named .asm sources
+/// * `load` - reads the contents of an included file
+/// * `options` - assembler options
+pub fn assemble_sources(
+    sources: &[Source],
+    load: &dyn Fn(&Path) -> io::Result<String>,
+    options: &Options) -> Result<Program, Vec<AssembleError>> {
+
+    let maps = Maps::new();
+    let mut symbol_map = builtin_symbols();
+    let mut errors: Vec<AssembleError> = Vec::new();
+    let mut labels: Vec<(String, u16)> = Vec::new();
+    let mut variables: Vec<(String, u16)> = Vec::new();
+
+    let source_lines = read_lines(sources, load, &mut errors);
+    let parsed = parse_lines(&source_lines, options, &mut errors);
+
+    // first pass: add labels to symbol table, then equ constants, which may use labels.
+    // The program starts after the prologue that stores the data words.
+    let prologue_size = data::prologue_size(&parsed.data);
+    let end = define_labels(&parsed.program, prologue_size as i32, &mut symbol_map, &mut labels, &mut errors);
+    // references to labels past the end of ROM are already reported as RomFull
+    let past_rom: HashSet<String> = symbol_map.iter()
+        .filter(|(_, address)| end > ROM_SIZE as i32 && **address >= ROM_SIZE as i32)
+        .map(|(name, _)| name.to_string())
+        .collect();
+    define_constants(&parsed.equs, &mut symbol_map, &mut errors);
+
+    // expressions may only use labels, builtins and equ constants
+    let constants = symbol_map.clone();
+    let lookup = |line: &SourceLine, symbol: &str| constants.get(qualify(symbol, &line.file).as_ref()).copied();
+    let data_words = data::evaluate(&parsed.data, &lookup, &mut errors);
+    let data = data::ranges(&data_words);
+
+    // variables skip RAM set by data directives
+    let next_free = |mut address: u16| {
+        while address < SCREEN_START && data.iter().any(|(start, count)| (*start..*start + *count).contains(&address)) {
+            address += 1;
+        }
+        address
+    };
+
+    // the prologue stores the data words, then jumps to the program
+    let mut words: Vec<u16> = Vec::new();
+    let mut lines: Vec<SourceLine> = Vec::new();
+    let mut prologue = |line: &SourceLine, bits: &[u16]| {
+        if (words.len()..words.len() + bits.len()).contains(&ROM_SIZE) {
+            let stripped_line = remove_comments(&line.text);
+            errors.push(LineError::new(ErrorKind::RomFull, stripped_line, stripped_line)
+                .locate(line, stripped_line));
+        }
+        words.extend_from_slice(bits);
+        lines.extend(bits.iter().map(|_| line.clone()));
+    };
+    for (line, address, value) in &data_words {
+        prologue(line, &data::store(*address, *value));
+    }
+    if let Some((line, _, _)) = data_words.last() {
+        prologue(line, &data::jump(prologue_size as u16));
+    }
+
+    // second pass: resolve operands to numbers, convert to bits
+    let mut scope = local::Scope::default();
+    let mut var_count = next_free(VARIABLE_START);
+    for (line, stripped_line, instruction) in &parsed.program {
+        let operand_text = &stripped_line[1..];
+
+        let bits = match instruction {
+            Instruction::A(Operand::Symbol(symbol)) => match scope.resolve(line, symbol) {
+                Ok(name) => {
+                    // put var number in symbol table if not already
+                    let key = qualify(name, &line.file);
+                    match symbol_map.get(key.as_ref()) {
+                        Some(_) if past_rom.contains(key.as_ref()) => Ok(0),
+                        Some(value) => check_a_value(*value, stripped_line, operand_text),
+                        None if local::is_scoped(symbol) => {
+                            Err(LineError::new(ErrorKind::UndefinedLabel, stripped_line, operand_text))
+                        },
+                        None if var_count == SCREEN_START => {
+                            Err(LineError::new(ErrorKind::RamFull, stripped_line, operand_text))
+                        },
+                        None => {
+                            let address = var_count;
+                            symbol_map.insert(key.clone(), address as i32);
+                            variables.push((key.into_owned(), address));
+                            var_count = next_free(address + 1); // increment variables declared
+                            Ok(address)
+                        }
+                    }
+                },
+                Err(kind) => Err(LineError::new(kind, stripped_line, operand_text))
+            },
+            Instruction::A(Operand::Expression(expr)) => {
+                let lookup = |symbol: &str| scope.resolve(line, symbol).ok()
+                    .and_then(|name| constants.get(qualify(name, &line.file).as_ref()).copied());
+                expr::evaluate(expr, &lookup)
+                    .map_err(|e| e.within(stripped_line, operand_text))
+                    .and_then(|value| check_a_value(value, stripped_line, operand_text))
+            },
+            Instruction::Label(symbol) => {
+                let _ = scope.declare(line, symbol); // errors were found defining labels
+                continue // no bits for labels
+            },
+            _ => Ok(get_bits(instruction, &maps).unwrap())
+        };
+
+        match bits {
+            Ok(bits) => {
+                if words.len() == ROM_SIZE {
+                    errors.push(LineError::new(ErrorKind::RomFull, stripped_line, stripped_line)
+                        .locate(line, stripped_line));
+                }
+                words.push(bits);
+                lines.push((*line).clone());
+            },
+            Err(e) => errors.push(e.locate(line, stripped_line))
+        }
+    }
+
+    if errors.is_empty() {
+        let warnings = lint::lint(&parsed.instructions, &parsed.equs, &parsed.data, &builtin_symbols(), &variables);
+        Ok(Program { words, lines, labels, variables, warnings, data })
+    } else {
+        Err(errors)
+    }
+}
+
+
+/// Assembles Hack assembly sources into a relocatable object. Labels get
+/// addresses relative to the start of the object, and symbols that are
+/// neither labels, builtins nor equ constants are left for the linker,
+/// which allocates variables across all objects.
+///
+/// # Arguments
+///
+/// * `name` - name of the object, used in link errors
+/// * `sources` - named .asm sources
+/// * `load` - reads the contents of an included file
+/// * `options` - assembler options
+pub fn assemble_object(
+    name: &str,
+    sources: &[Source],
+    load: &dyn Fn(&Path) -> io::Result<String>,
+    options: &Options) -> Result<object::Object, Vec<AssembleError>> {
+
+    let maps = Maps::new();
+    let mut errors: Vec<AssembleError> = Vec::new();
+    let mut object = object::Object { name: name.to_string(), ..Default::default() };
+
+    let source_lines = read_lines(sources, load, &mut errors);
+    let parsed = parse_lines(&source_lines, options, &mut errors);
+
+    // labels are relocated by the linker, so constants can't depend on them.
+    // Labels with the name of a builtin are ignored, as in a program.
+    let mut label_map: SymbolMap = builtin_symbols();
+    define_labels(&parsed.program, 0, &mut label_map, &mut object.labels, &mut errors);
+
+    // a prologue would only run for the first object
+    for (line, stripped_line, _) in &parsed.data {
+        errors.push(LineError::new(ErrorKind::DataInObject, stripped_line, stripped_line).locate(line, stripped_line));
+    }
+    let mut constants = builtin_symbols();
+    define_constants(&parsed.equs, &mut constants, &mut errors);
+
+    let mut scope = local::Scope::default();
+    for (line, stripped_line, instruction) in &parsed.program {
+        let operand_text = &stripped_line[1..];
+        let address = object.words.len() as u16;
+
+        let bits = match instruction {
+            Instruction::A(Operand::Symbol(symbol)) => match scope.resolve(line, symbol) {
+                Ok(name) => {
+                    let key = qualify(name, &line.file);
+                    if let Some(value) = constants.get(key.as_ref()) {
+                        check_a_value(*value, stripped_line, operand_text)
+                    } else if let Some(value) = label_map.get(key.as_ref()) {
+                        object.relocations.push(address);
+                        Ok(*value as u16)
+                    } else if local::is_scoped(symbol) {
+                        Err(LineError::new(ErrorKind::UndefinedLabel, stripped_line, operand_text))
+                    } else {
+                        object.references.push((address, key.into_owned()));
+                        Ok(0)
+                    }
+                },
+                Err(kind) => Err(LineError::new(kind, stripped_line, operand_text))
+            },
+            Instruction::A(Operand::Expression(expr)) => {
+                let key = |symbol: &str| scope.resolve(line, symbol).ok().map(|name| qualify(name, &line.file).into_owned());
+                let lookup = |symbol: &str| key(symbol).and_then(|key| constants.get(key.as_str()).copied());
+                expr::evaluate(expr, &lookup)
+                    .map_err(|mut e| {
+                        if e.kind == ErrorKind::UndefinedSymbol && key(&e.text).is_some_and(|key| label_map.contains_key(key.as_str())) {
+                            e.kind = ErrorKind::RelocatableExpression;
+                        }
+                        e.within(stripped_line, operand_text)
+                    })
+                    .and_then(|value| check_a_value(value, stripped_line, operand_text))
+            },
+            Instruction::Label(symbol) => {
+                let _ = scope.declare(line, symbol); // errors were found defining labels
+                continue // no bits for labels
+            },
+            _ => Ok(get_bits(instruction, &maps).unwrap())
+        };
+
+        match bits {
+            Ok(bits) => object.words.push(bits),
+            Err(e) => errors.push(e.locate(line, stripped_line))
+        }
+    }
+
+    if errors.is_empty() {
+        // labels may be used by other objects
+        object.warnings = lint::lint(&parsed.instructions, &parsed.equs, &parsed.data, &builtin_symbols(), &[]).into_iter()
+            .filter(|warning| warning.kind != lint::WarningKind::UnusedLabel)
+            .collect();
+        Ok(object)
+    } else {
+        Err(errors)
+    }
+}
+
+#[test]
+fn test_assemble() {
+    let source = "// Computes R0 = 2 + 3\n\
+        @2\n\
+        D=A\n\
+        @3\n\
+        D=D+A\n\
+        @0\n\
+        M=D\n";
+    let expected = vec![
+        "0000000000000010",
+        "1110110000010000",
+        "0000000000000011",
+        "1110000010010000",
+        "0000000000000000",
+        "1110001100001000",
+    ];
+    assert_eq!(expected, assemble(source).unwrap().to_hack());
+
+    // labels and variables
+    let source = "(LOOP)\n@i\nM=M+1\n@LOOP\n0;JMP\n(END)\n@END";
+    let program = assemble(source).unwrap();
+    assert_eq!(vec![16, 0b1111110111001000, 0, 0b1110101010000111, 4], program.words);
+    assert_eq!(vec![2, 3, 4, 5, 7], program.lines.iter().map(|line| line.number).collect::<Vec<usize>>());
+    assert_eq!(vec![("LOOP".to_string(), 0), ("END".to_string(), 4)], program.labels);
+    assert_eq!(vec![("i".to_string(), 16)], program.variables);
+}
+
+#[test]
+fn test_assemble_errors() {
+    let error = assemble("@1\n  M=D+D // comment\n").unwrap_err();
+    let expected = AssembleError {
+        kind: ErrorKind::UnknownComp,
+        file: String::new(),
+        line: 2,
+        column: 5,
+        text: "D+D".to_string(),
+        source: "  M=D+D // comment".to_string(),
+    };
+    assert_eq!(expected, error);
+    assert_eq!("2:5: no mapping found for comp mnemonic `D+D`", error.to_string());
+
+    let error = assemble("\n\n\t(LOOP) x").unwrap_err();
+    assert_eq!((ErrorKind::InvalidLCommand, 3, 9, "x"), (error.kind, error.line, error.column, error.text.as_str()));
+}
+
+#[test]
+fn test_assemble_all() {
+    let source = "(1LOOP)\n@x y\nD=D\nAM=M-1\nD;JGQ\n0;JMP";
+    let errors = assemble_all(source).unwrap_err();
+    let found: Vec<(usize, usize, &str)> = errors.iter()
+        .map(|e| (e.line, e.column, e.text.as_str()))
+        .collect();
+    assert_eq!(vec![(1, 1, "(1LOOP)"), (2, 4, "y"), (5, 3, "JGQ")], found);
+}
+
+#[test]
+fn test_assemble_malformed() {
+    let errors = assemble_all("M=Q\nMMD=D\nD;JMP junk\n@i j\nM=D+\n(LOOP\n").unwrap_err();
+    let found: Vec<(ErrorKind, &str)> = errors.iter().map(|e| (e.kind, e.text.as_str())).collect();
+    assert_eq!(vec![
+        (ErrorKind::UnknownComp, "Q"),
+        (ErrorKind::UnknownDest, "MMD"),
+        (ErrorKind::InvalidCCommand, "junk"),
+        (ErrorKind::InvalidACommand, "j"),
+        (ErrorKind::UnknownComp, "D+"),
+        (ErrorKind::InvalidLCommand, "(LOOP"),
+    ], found);
+}
+
+#[test]
+#[cfg(feature = "extended-alu")]
+fn test_assemble_shifts() {
+    let program = assemble("D=D<<\nAM=M>>\nD=A <<;JGT").unwrap();
+    assert_eq!(vec![0b1010110000010000, 0b1011000000101000, 0b1010100000010001], program.words);
+}
+
+#[test]
+#[cfg(not(feature = "extended-alu"))]
+fn test_assemble_shifts() {
+    let error = assemble("D=D<<").unwrap_err();
+    assert_eq!((ErrorKind::UnknownComp, "D<<"), (error.kind, error.text.as_str()));
+}
+
+#[test]
+fn test_assemble_capacity() {
+    let source = "@1\n".repeat(ROM_SIZE) + "(END)\nD=0\nD=1";
+    let errors = assemble_all(&source).unwrap_err();
+    let found: Vec<(ErrorKind, usize)> = errors.iter().map(|e| (e.kind, e.line)).collect();
+    assert_eq!(vec![(ErrorKind::RomFull, ROM_SIZE + 2)], found);
+
+    // references to labels past the end of ROM only report RomFull
+    let source = "@END\n@LAST\n".to_string() + &"@1\n".repeat(ROM_SIZE - 2) + "(END)\nD=0\n@END\n0;JMP\n(LAST)";
+    let errors = assemble_all(&source).unwrap_err();
+    let found: Vec<(ErrorKind, usize)> = errors.iter().map(|e| (e.kind, e.line)).collect();
+    assert_eq!(vec![(ErrorKind::RomFull, ROM_SIZE + 2)], found);
+
+    // unless no instruction is past the end of ROM
+    let source = "@END\n".to_string() + &"@1\n".repeat(ROM_SIZE - 1) + "(END)";
+    let errors = assemble_all(&source).unwrap_err();
+    let found: Vec<(ErrorKind, usize)> = errors.iter().map(|e| (e.kind, e.line)).collect();
+    assert_eq!(vec![(ErrorKind::NumberOutOfRange, 1)], found);
+
+    let variables = (SCREEN_START - VARIABLE_START) as usize;
+    let source: String = (0..=variables).map(|idx| format!("@v{}\n", idx)).collect();
+    let errors = assemble_all(&source).unwrap_err();
+    let found: Vec<(ErrorKind, usize, &str)> = errors.iter().map(|e| (e.kind, e.line, e.text.as_str())).collect();
+    let last = format!("v{}", variables);
+    assert_eq!(vec![(ErrorKind::RamFull, variables + 1, last.as_str())], found);
+}
+
+#[test]
+fn test_assemble_expressions() {
+    let source = ".equ ROW 32\n\
+        .equ ARRAY_BASE END+1\n\
+        (LOOP)\n\
+        @SCREEN+ROW*5\n\
+        @ARRAY_BASE+3\n\
+        @END-1\n\
+        (END)\n\
+        @(END)+'0'";
+    assert_eq!(vec![16544, 7, 2, 51], assemble(source).unwrap().words);
+
+    let errors = assemble_all(".equ X 1\n.equ X 2\n@i\n@i+1\n.equ Y\n@1/0\n@X-2\n.equ BIG 0x8000\n@BIG").unwrap_err();
+    let found: Vec<(ErrorKind, usize, usize)> = errors.iter().map(|e| (e.kind, e.line, e.column)).collect();
+    assert_eq!(vec![
+        (ErrorKind::DuplicateSymbol, 2, 6),
+        (ErrorKind::InvalidEqu, 5, 1),
+        (ErrorKind::UndefinedSymbol, 4, 2),
+        (ErrorKind::DivisionByZero, 6, 4),
+        (ErrorKind::NumberOutOfRange, 7, 2),
+        (ErrorKind::NumberOutOfRange, 9, 2),
+    ], found);
+}
+
+#[test]
+fn test_assemble_data() {
+    // the prologue stores each word, then the program starts after it
+    let source = ".equ TABLE 100\n\
+        .data TABLE\n\
+        .word START, -1\n\
+        (START)\n\
+        @i\n\
+        .data 16\n\
+        .string \"A\"";
+    let program = assemble(source).unwrap();
+    let mut prologue: Vec<u16> = [(100, 18), (101, 0xffff), (16, 'A' as u16), (17, 0)].iter()
+        .flat_map(|(address, value)| data::store(*address, *value).to_vec())
+        .collect();
+    prologue.extend_from_slice(&data::jump(18));
+    assert_eq!(prologue, program.words[..18].to_vec());
+    assert_eq!(vec![("START".to_string(), 18)], program.labels);
+    assert_eq!(vec![("i".to_string(), 18)], program.variables);
+    assert_eq!(vec![18], program.words[18..].to_vec());
+    assert_eq!(vec![(100, 2), (16, 2)], program.data);
+    assert_eq!(3, program.lines[4].number);
+    assert_eq!(7, program.lines[17].number);
+
+    // the jump at the end of the prologue must fit in ROM too
+    let source = ".data 16\n.word ".to_string() + &"0, ".repeat(ROM_SIZE / data::WORD_SIZE - 1) + "0\n@1";
+    let errors = assemble_all(&source).unwrap_err();
+    let found: Vec<(ErrorKind, usize)> = errors.iter().map(|e| (e.kind, e.line)).collect();
+    assert_eq!(vec![(ErrorKind::RomFull, 2)], found);
+
+    let errors = assemble_all(".word 1\n.data NOPE\n.word 1\n.data 32767\n.word 1, 2\n.word 65536\n.string HI").unwrap_err();
+    let found: Vec<(ErrorKind, usize, usize)> = errors.iter().map(|e| (e.kind, e.line, e.column)).collect();
+    assert_eq!(vec![
+        (ErrorKind::InvalidData, 7, 1),
+        (ErrorKind::DataWithoutAddress, 1, 1),
+        (ErrorKind::UndefinedSymbol, 2, 7),
+        (ErrorKind::NumberOutOfRange, 5, 10),
+        (ErrorKind::NumberOutOfRange, 6, 7),
+    ], found);
+}
+
+#[test]
+fn test_assemble_local_labels() {
+    let source = "(MULT)\n\
+        (.loop)\n\
+        @.end\n\
+        0;JMP\n\
+        (.end)\n\
+        @.loop\n\
+        (DIV)\n\
+        (.loop)\n\
+        @.loop+1\n\
+        (1)\n\
+        @1f\n\
+        (1)\n\
+        @1b\n\
+        @MULT.end";
+    let program = assemble(source).unwrap();
+    assert_eq!(vec![2, 0b1110101010000111, 0, 4, 5, 5, 2], program.words);
+    let labels: Vec<&str> = program.labels.iter().map(|(label, _)| label.as_str()).collect();
+    assert_eq!(vec!["MULT", "MULT.loop", "MULT.end", "DIV", "DIV.loop"], labels);
+    assert!(program.variables.is_empty());
+    let unused: Vec<&str> = program.warnings.iter().map(|w| w.text.as_str()).collect();
+    assert_eq!(vec!["MULT", "DIV", "1"], unused);
+
+    let errors = assemble_all("(.loop)\n@1b\n(START)\n@.nope\n@1f\n@.x+1").unwrap_err();
+    let found: Vec<(ErrorKind, usize, &str)> = errors.iter().map(|e| (e.kind, e.line, e.text.as_str())).collect();
+    assert_eq!(vec![
+        (ErrorKind::LocalWithoutGlobal, 1, ".loop"),
+        (ErrorKind::UndefinedLabel, 2, "1b"),
+        (ErrorKind::UndefinedLabel, 4, ".nope"),
+        (ErrorKind::UndefinedLabel, 5, "1f"),
+        (ErrorKind::UndefinedSymbol, 6, ".x"),
+    ], found);
+}
+
+#[test]
+fn test_assemble_sources() {
+    let load = |path: &Path| match path.to_str().unwrap() {
+        "lib/zero.asm" => Ok("(ZERO)\n(_loop)\n@_loop\n0;JMP".to_string()),
+        _ => Err(io::Error::new(io::ErrorKind::NotFound, "not found"))
+    };
+    let sources = vec![
+        Source { name: "main.asm".to_string(), contents: "(_loop)\n@_loop\n@ZERO\n@_x".to_string() },
+        Source { name: "lib.asm".to_string(), contents: ".include \"lib/zero.asm\"\n@_x\n@_loop".to_string() },
+    ];
+    let program = assemble_sources(&sources, &load, &Options::default()).unwrap();
+    assert_eq!(vec![0, 3, 16, 3, 0b1110101010000111, 17, 18], program.words);
+    let labels: Vec<&str> = program.labels.iter().map(|(label, _)| label.as_str()).collect();
+    assert_eq!(vec!["_loop@main.asm", "ZERO", "_loop@lib/zero.asm"], labels);
+
+    let sources = vec![
+        Source { name: "main.asm".to_string(), contents: "@1\n.include \"lib/zero.asm\"\n.include \"nope.asm\"".to_string() },
+    ];
+    let load = |path: &Path| match path.to_str().unwrap() {
+        "lib/zero.asm" => Ok("@NOPE+1".to_string()),
+        _ => Err(io::Error::new(io::ErrorKind::NotFound, "not found"))
+    };
+    let errors = assemble_sources(&sources, &load, &Options::default()).unwrap_err();
+    let found: Vec<(ErrorKind, &str, usize)> = errors.iter().map(|e| (e.kind, e.file.as_str(), e.line)).collect();
+    assert_eq!(vec![(ErrorKind::IncludeNotFound, "main.asm", 3), (ErrorKind::UndefinedSymbol, "lib/zero.asm", 1)], found);
+}
+
+#[test]
+fn test_assemble_object() {
+    let load = |_: &Path| Err(io::Error::new(io::ErrorKind::NotFound, "not found"));
+    let source = ".equ SIZE 2*8\n(LOOP)\n@SIZE\n@LOOP\n@_end\n@MULT\n(_end)\n@SCREEN+SIZE\n";
+    let sources = vec![Source { name: "main.asm".to_string(), contents: source.to_string() }];
+    let object = assemble_object("main.hobj", &sources, &load, &Options::default()).unwrap();
+    assert_eq!(vec![16, 0, 4, 0, 16400], object.words);
+    assert_eq!(vec![("LOOP".to_string(), 0), ("_end@main.asm".to_string(), 4)], object.labels);
+    assert_eq!(vec![1, 2], object.relocations);
+    assert_eq!(vec![(3, "MULT".to_string())], object.references);
+
+    // labels move when linked, so expressions can't use them
+    let sources = vec![Source { name: "main.asm".to_string(), contents: "(LOOP)\n@LOOP+1\n@NOPE+1\n".to_string() }];
+    let errors = assemble_object("main.hobj", &sources, &load, &Options::default()).unwrap_err();
+    let found: Vec<(ErrorKind, usize, &str)> = errors.iter().map(|e| (e.kind, e.line, e.text.as_str())).collect();
+    assert_eq!(vec![(ErrorKind::RelocatableExpression, 2, "LOOP"), (ErrorKind::UndefinedSymbol, 3, "NOPE")], found);
+
+    // local labels are exported under their global label, anonymous labels are not exported
+    let sources = vec![Source { name: "mult.asm".to_string(), contents: "(MULT)\n(.loop)\n(1)\n@.loop\n@1b\n".to_string() }];
+    let object = assemble_object("mult.hobj", &sources, &load, &Options::default()).unwrap();
+    let labels: Vec<&str> = object.labels.iter().map(|(label, _)| label.as_str()).collect();
+    assert_eq!(vec!["MULT", "MULT.loop"], labels);
+    assert_eq!(vec![0, 1], object.relocations);
+
+    let sources = vec![Source { name: "main.asm".to_string(), contents: "@1\n.data 16\n".to_string() }];
+    let errors = assemble_object("main.hobj", &sources, &load, &Options::default()).unwrap_err();
+    assert_eq!((ErrorKind::DataInObject, 2), (errors[0].kind, errors[0].line));
+}
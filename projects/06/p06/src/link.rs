@@ -0,0 +1,192 @@
+// Linker combining relocatable Hack objects into one program
+// Author: Leo Robinovitch
+
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::error::Error;
+use std::fmt;
+
+use crate::object::Object;
+use crate::{builtin_symbols, Program, SymbolMap, ROM_SIZE, SCREEN_START, VARIABLE_START};
+
+/// The kind of problem found while linking objects
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum LinkErrorKind {
+    InvalidObject,
+    DuplicateLabel,
+    RomFull,
+    RamFull,
+}
+
+impl fmt::Display for LinkErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            LinkErrorKind::InvalidObject => "invalid object file line",
+            LinkErrorKind::DuplicateLabel => "label defined in more than one object",
+            LinkErrorKind::RomFull => "program does not fit in the 32768 words of ROM, linking",
+            LinkErrorKind::RamFull => "no RAM left for variable below the screen at 16384",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+
+/// Error returned by `link`, located in an object
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct LinkError {
+    pub kind: LinkErrorKind,
+    /// name of the object the error is in
+    pub object: String,
+    /// the offending text
+    pub text: String,
+}
+
+impl LinkError {
+    /// Returns a LinkError in the named object
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - the kind of error
+    /// * `object` - name of the object
+    /// * `text` - the offending text
+    pub(crate) fn new(kind: LinkErrorKind, object: &str, text: &str) -> LinkError {
+        LinkError { kind, object: object.to_string(), text: text.to_string() }
+    }
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} `{}`", self.object, self.kind, self.text)
+    }
+}
+
+impl Error for LinkError {}
+
+
+/// Links objects into one program, in order, returning every error found.
+/// Each object is placed after the previous one in ROM, labels are shared
+/// between objects and symbols that are not labels are allocated as
+/// variables from VARIABLE_START in order of first reference. The linked
+/// program has no source lines or warnings.
+///
+/// # Arguments
+///
+/// * `objects` - objects from `assemble_object`
+pub fn link(objects: &[Object]) -> Result<Program, Vec<LinkError>> {
+    let mut errors: Vec<LinkError> = Vec::new();
+    let mut symbol_map: SymbolMap = builtin_symbols();
+    let mut labels: Vec<(String, u16)> = Vec::new();
+    let mut variables: Vec<(String, u16)> = Vec::new();
+
+    // first pass: place objects and add their labels to the symbol table.
+    // Labels with the name of a builtin are ignored, as when assembling.
+    let builtins = builtin_symbols();
+    let mut bases: Vec<usize> = Vec::new();
+    let mut size = 0;
+    for object in objects {
+        bases.push(size);
+        for (label, address) in object.labels.iter().filter(|(label, _)| !builtins.contains_key(label.as_str())) {
+            let address = (size + *address as usize) as u16;
+            match symbol_map.entry(Cow::Borrowed(label)) {
+                Entry::Vacant(entry) => {
+                    entry.insert(address as i32);
+                    labels.push((label.clone(), address));
+                },
+                Entry::Occupied(_) => errors.push(LinkError::new(LinkErrorKind::DuplicateLabel, &object.name, label))
+            }
+        }
+        size += object.words.len();
+        if size > ROM_SIZE && size - object.words.len() <= ROM_SIZE {
+            errors.push(LinkError::new(LinkErrorKind::RomFull, &object.name, &object.name));
+        }
+    }
+
+    // second pass: relocate addresses and resolve references
+    let mut words: Vec<u16> = Vec::new();
+    let mut var_count = VARIABLE_START;
+    for (object, base) in objects.iter().zip(bases) {
+        let mut object_words = object.words.clone();
+        for address in &object.relocations {
+            object_words[*address as usize] = object_words[*address as usize].wrapping_add(base as u16);
+        }
+        for (address, symbol) in &object.references {
+            object_words[*address as usize] = match symbol_map.entry(Cow::Borrowed(symbol)) {
+                Entry::Occupied(entry) => *entry.get() as u16,
+                Entry::Vacant(_) if var_count == SCREEN_START => {
+                    errors.push(LinkError::new(LinkErrorKind::RamFull, &object.name, symbol));
+                    continue;
+                },
+                Entry::Vacant(entry) => {
+                    entry.insert(var_count as i32);
+                    variables.push((symbol.clone(), var_count));
+                    var_count += 1; // increment variables declared
+                    var_count - 1
+                }
+            };
+        }
+        words.extend(object_words);
+    }
+
+    if errors.is_empty() {
+        Ok(Program { words, labels, variables, ..Default::default() })
+    } else {
+        Err(errors)
+    }
+}
+
+#[test]
+fn test_link() {
+    let object = |name: &str, source: &str| {
+        crate::assemble_object(
+            name,
+            &[crate::Source { name: name.to_string(), contents: source.to_string() }],
+            &|_| Err(std::io::Error::new(std::io::ErrorKind::NotFound, "not found")),
+            &crate::Options::default()).unwrap()
+    };
+
+    // linking objects gives the same program as assembling the sources together
+    let main = "@i\nM=1\n(_loop)\n@MULT\n0;JMP\n@_loop\n0;JMP\n@R0\n";
+    let mult = "(MULT)\n@i\nD=M\n@_loop\n(_loop)\n@MULT\n@x\n";
+    let objects = [object("main.asm", main), object("mult.asm", mult)];
+    let linked = link(&objects).unwrap();
+    let sources = [
+        crate::Source { name: "main.asm".to_string(), contents: main.to_string() },
+        crate::Source { name: "mult.asm".to_string(), contents: mult.to_string() },
+    ];
+    let assembled = crate::assemble_sources(
+        &sources,
+        &|_| Err(std::io::Error::new(std::io::ErrorKind::NotFound, "not found")),
+        &crate::Options::default()).unwrap();
+    assert_eq!(assembled.words, linked.words);
+    assert_eq!(assembled.labels, linked.labels);
+    assert_eq!(assembled.variables, linked.variables);
+
+    // objects survive a round trip through the object file format
+    let read: Vec<Object> = objects.iter()
+        .map(|object| Object::from_text(&object.name, &object.to_text().join("\n")).unwrap())
+        .collect();
+    assert_eq!(linked, link(&read).unwrap());
+
+    // labels are shared, so may only be defined once
+    let errors = link(&[object("a.asm", "(END)\n"), object("b.asm", "(END)\n(_x)\n"), object("c.asm", "(_x)\n")])
+        .unwrap_err();
+    assert_eq!(vec![LinkError::new(LinkErrorKind::DuplicateLabel, "b.asm", "END")], errors);
+
+    // labels with the name of a builtin are ignored with a warning, as when assembling
+    let shadowing = object("a.asm", "(SP)\n@SP\n0;JMP\n");
+    assert!(shadowing.labels.is_empty());
+    let kinds: Vec<crate::lint::WarningKind> = shadowing.warnings.iter().map(|w| w.kind).collect();
+    assert_eq!(vec![crate::lint::WarningKind::ShadowedBuiltin], kinds);
+    let assembled = crate::assemble_all("(SP)\n@SP\n0;JMP\n").unwrap();
+    assert_eq!(assembled.words, link(&[shadowing]).unwrap().words);
+    let written = Object::from_text("a.hobj", "HOBJ 1\nW 0000000000000000\nL 0 SP\nL 1 END\n").unwrap();
+    assert_eq!(vec![("END".to_string(), 1)], link(&[written]).unwrap().labels);
+
+    let source: String = (16..16384).map(|idx| format!("@v{}\n", idx)).collect();
+    let errors = link(&[object("a.asm", &source), object("b.asm", "@w\n")]).unwrap_err();
+    assert_eq!(vec![LinkError::new(LinkErrorKind::RamFull, "b.asm", "w")], errors);
+
+    let full = Object { name: "full".to_string(), words: vec![0; ROM_SIZE], ..Default::default() };
+    let errors = link(&[full, object("a.asm", "@1\n")]).unwrap_err();
+    assert_eq!(vec![LinkError::new(LinkErrorKind::RomFull, "a.asm", "a.asm")], errors);
+}
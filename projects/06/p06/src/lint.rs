@@ -0,0 +1,217 @@
+// Lint pass for Hack assembly: likely mistakes that still assemble
+// Author: Leo Robinovitch
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::data::Directive;
+use crate::local::Scope;
+use crate::parser::{Instruction, Operand};
+use crate::{expr, get_equ, qualify, SourceLine, SymbolMap, STACK_START};
+
+/// The kind of likely mistake found by the lint pass
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum WarningKind {
+    DuplicateLabel,
+    ShadowedBuiltin,
+    UnusedLabel,
+    SingleUseVariable,
+    JumpWritesA,
+    VariableInStack,
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            WarningKind::DuplicateLabel => "label already defined, this definition is ignored",
+            WarningKind::ShadowedBuiltin => "label has the name of a predefined symbol and is ignored",
+            WarningKind::UnusedLabel => "label is never referenced",
+            WarningKind::SingleUseVariable => "variable is only referenced once",
+            WarningKind::JumpWritesA => "C command writes A and jumps, so it jumps to the old A",
+            WarningKind::VariableInStack => "variable allocated at 256 or above, where the VM stack starts",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+
+/// Warning found by the lint pass, located in the source
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Warning {
+    pub kind: WarningKind,
+    /// name of the file the warning is in
+    pub file: String,
+    /// line number in the file, starting at 1
+    pub line: usize,
+    /// column of the offending text in the line, starting at 1
+    pub column: usize,
+    /// the offending text
+    pub text: String,
+    /// the source line, after macro expansion
+    pub source: String,
+}
+
+impl Warning {
+    /// Returns a Warning for `text`, which must be a slice of the line's text
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - the kind of warning
+    /// * `line` - the source line
+    /// * `text` - the offending slice of the line's text
+    fn new(kind: WarningKind, line: &SourceLine, text: &str) -> Warning {
+        let start = text.as_ptr() as usize - line.text.as_ptr() as usize;
+        Warning {
+            kind,
+            file: line.file.to_string(),
+            line: line.number,
+            column: line.text[..start].chars().count() + 1,
+            text: text.to_string(),
+            source: line.text.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.file.is_empty() {
+            write!(f, "{}:", self.file)?;
+        }
+        write!(f, "{}:{}: {} `{}`", self.line, self.column, self.kind, self.text)
+    }
+}
+
+
+/// Returns warnings for likely mistakes in a program that assembled
+///
+/// # Arguments
+///
+/// * `instructions` - parsed instructions with their source and stripped lines
+/// * `equs` - equ directives with their source lines
+/// * `data` - data directives with their source and stripped lines
+/// * `builtins` - predefined symbols
+/// * `variables` - allocated variables, by qualified name
+pub(crate) fn lint<'a>(
+    instructions: &'a [(&'a SourceLine, &'a str, Instruction<'a>)],
+    equs: &'a [(&'a SourceLine, &'a str)],
+    data: &'a [(&'a SourceLine, &'a str, Directive<'a>)],
+    builtins: &SymbolMap,
+    variables: &[(String, u16)]) -> Vec<Warning> {
+
+    let mut warnings: Vec<Warning> = Vec::new();
+
+    // declarations and references of each qualified symbol, in source order
+    let mut declared: HashSet<String> = HashSet::new();
+    let mut labels: Vec<(String, &SourceLine, &str)> = Vec::new();
+    let mut references: HashMap<Cow<str>, Vec<(&SourceLine, &str)>> = HashMap::new();
+    let mut reference = |symbol: Cow<'a, str>, line: &'a SourceLine, text: &'a str| {
+        references.entry(qualify(symbol, &line.file)).or_default().push((line, text));
+    };
+
+    let mut scope = Scope::default();
+    for (line, stripped_line, instruction) in instructions {
+        match instruction {
+            Instruction::Label(label) => {
+                let key = match scope.declare(line, label) {
+                    Ok(name) => qualify(name, &line.file).into_owned(),
+                    Err(_) => continue
+                };
+                if builtins.contains_key(key.as_str()) {
+                    warnings.push(Warning::new(WarningKind::ShadowedBuiltin, line, label));
+                } else if !declared.insert(key.clone()) {
+                    warnings.push(Warning::new(WarningKind::DuplicateLabel, line, label));
+                } else {
+                    labels.push((key, line, label));
+                }
+            },
+            Instruction::A(Operand::Symbol(symbol)) => {
+                if let Ok(name) = scope.resolve(line, symbol) {
+                    reference(name, line, &stripped_line[1..]);
+                }
+            },
+            Instruction::A(Operand::Expression(_)) => {
+                for symbol in expr::symbols(&stripped_line[1..]) {
+                    if let Ok(name) = scope.resolve(line, symbol) {
+                        reference(name, line, symbol);
+                    }
+                }
+            },
+            Instruction::C { dest, jump, .. } => {
+                if dest.contains('A') && !jump.is_empty() {
+                    warnings.push(Warning::new(WarningKind::JumpWritesA, line, stripped_line));
+                }
+            },
+            Instruction::A(Operand::Number(_)) => {}
+        }
+    }
+    for (line, stripped_line) in equs {
+        if let Ok((_, expr)) = get_equ(stripped_line) {
+            for symbol in expr::symbols(expr) {
+                reference(symbol.into(), line, symbol);
+            }
+        }
+    }
+
+    for (line, _, directive) in data {
+        let exprs = match directive {
+            Directive::Data(expr) => std::slice::from_ref(expr),
+            Directive::Word(exprs) => exprs.as_slice(),
+            Directive::String(_) => &[],
+        };
+        for symbol in exprs.iter().flat_map(|expr| expr::symbols(expr)) {
+            reference(symbol.into(), line, symbol);
+        }
+    }
+
+    for (label, line, text) in labels {
+        if !references.contains_key(label.as_str()) {
+            warnings.push(Warning::new(WarningKind::UnusedLabel, line, text));
+        }
+    }
+    for (variable, _) in variables {
+        if let Some([(line, text)]) = references.get(variable.as_str()).map(Vec::as_slice) {
+            warnings.push(Warning::new(WarningKind::SingleUseVariable, line, text));
+        }
+    }
+    let in_stack = variables.iter().find(|(_, address)| *address >= STACK_START);
+    if let Some((line, text)) = in_stack.and_then(|(variable, _)| references.get(variable.as_str())?.first()) {
+        warnings.push(Warning::new(WarningKind::VariableInStack, line, text));
+    }
+
+    warnings
+}
+
+#[test]
+fn test_lint() {
+    let source = "(SP)\n\
+        (LOOP)\n\
+        @i\n\
+        M=M+1\n\
+        @counter\n\
+        @i\n\
+        (LOOP)\n\
+        (END)\n\
+        @LOOP\n\
+        AM=M-1;JMP\n";
+    let program = crate::assemble(source).unwrap();
+    let found: Vec<(WarningKind, usize, usize, &str)> = program.warnings.iter()
+        .map(|w| (w.kind, w.line, w.column, w.text.as_str()))
+        .collect();
+    assert_eq!(vec![
+        (WarningKind::ShadowedBuiltin, 1, 2, "SP"),
+        (WarningKind::DuplicateLabel, 7, 2, "LOOP"),
+        (WarningKind::JumpWritesA, 10, 1, "AM=M-1;JMP"),
+        (WarningKind::UnusedLabel, 8, 2, "END"),
+        (WarningKind::SingleUseVariable, 5, 2, "counter"),
+    ], found);
+
+    let program = crate::assemble(".equ BASE END+1\n(END)\n@BASE\n@SCREEN+BASE\n").unwrap();
+    assert!(program.warnings.is_empty());
+
+    let source: String = (0..250).map(|idx| format!("@v{}\n@v{}\n", idx, idx)).collect();
+    let program = crate::assemble(&source).unwrap();
+    let found: Vec<(WarningKind, usize)> = program.warnings.iter().map(|w| (w.kind, w.line)).collect();
+    assert_eq!(vec![(WarningKind::VariableInStack, 481)], found);
+}
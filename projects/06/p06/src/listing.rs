@@ -0,0 +1,132 @@
+// Listing (.lst) and symbol map (.sym) files for assembled Hack programs
+// Author: Leo Robinovitch
+
+use std::fmt::Write;
+
+use crate::{Program, ROM_SIZE};
+
+/// Returns the listing of a program: for each ROM address, the encoded
+/// bits, the hex value and the source line with its line number. Line
+/// numbers include the file name when the program spans several files.
+/// Linked programs have no source lines, so only their words are listed.
+///
+/// # Arguments
+///
+/// * `program` - assembled program
+pub fn listing(program: &Program) -> String {
+    let several_files = program.lines.iter().any(|line| line.file != program.lines[0].file);
+
+    let mut lst = String::from("ADDR   HEX   BITS              LINE  SOURCE\n");
+    for (address, word) in program.words.iter().enumerate() {
+        let line = match program.lines.get(address) {
+            Some(line) => line,
+            None => {
+                writeln!(lst, "{:05}  {:04X}  {:016b}", address, word, word).unwrap();
+                continue;
+            }
+        };
+        let location = if several_files {
+            format!("{}:{}", line.file, line.number)
+        } else {
+            line.number.to_string()
+        };
+        writeln!(lst, "{:05}  {:04X}  {:016b}  {:>4}  {}", address, word, word, location, line.text.trim()).unwrap();
+    }
+    lst
+}
+
+#[test]
+fn test_listing() {
+    let source = "// add\n@2\n  D=A // two\n";
+    let program = crate::assemble(source).unwrap();
+    let expected = "ADDR   HEX   BITS              LINE  SOURCE\n\
+        00000  0002  0000000000000010     2  @2\n\
+        00001  EC10  1110110000010000     3  D=A // two\n";
+    assert_eq!(expected, listing(&program));
+
+    // a linked program lists its words without source lines
+    let object = crate::assemble_object(
+        "add.hobj",
+        &[crate::Source { name: "add.asm".to_string(), contents: source.to_string() }],
+        &|_| Err(std::io::Error::new(std::io::ErrorKind::NotFound, "not found")),
+        &crate::Options::default()).unwrap();
+    let linked = crate::link::link(&[object]).unwrap();
+    let expected = "ADDR   HEX   BITS              LINE  SOURCE\n\
+        00000  0002  0000000000000010\n\
+        00001  EC10  1110110000010000\n";
+    assert_eq!(expected, listing(&linked));
+}
+
+
+/// Returns the symbol map of a program: the labels from the first pass
+/// with their ROM addresses, then the variables with their RAM addresses
+///
+/// # Arguments
+///
+/// * `program` - assembled program
+pub fn symbol_map(program: &Program) -> String {
+    let mut sym = String::from("// labels\n");
+    for (label, address) in &program.labels {
+        writeln!(sym, "{} {}", label, address).unwrap();
+    }
+    sym.push_str("// variables\n");
+    for (variable, address) in &program.variables {
+        writeln!(sym, "{} {}", variable, address).unwrap();
+    }
+    sym
+}
+
+#[test]
+fn test_symbol_map() {
+    let program = crate::assemble("(LOOP)\n@i\nM=M+1\n@LOOP\n0;JMP").unwrap();
+    assert_eq!("// labels\nLOOP 0\n// variables\ni 16\n", symbol_map(&program));
+}
+
+
+/// Returns a summary of the memory a program uses: its instruction
+/// count and ROM utilization, its variables with their RAM addresses and
+/// the RAM ranges set by data directives
+///
+/// # Arguments
+///
+/// * `program` - assembled program
+pub fn memory_map(program: &Program) -> String {
+    let used = program.words.len();
+    let mut map = format!("instructions: {}\n", used);
+    map.push_str(&format!("ROM: {} of {} words ({:.1}%)\n",
+        used, ROM_SIZE, 100.0 * used as f64 / ROM_SIZE as f64));
+    map.push_str(&format!("variables: {}\n", program.variables.len()));
+    for (variable, address) in &program.variables {
+        map.push_str(&format!("  {:>5}  {}\n", address, variable));
+    }
+    if !program.data.is_empty() {
+        let words: usize = program.data.iter().map(|(_, count)| *count as usize).sum();
+        map.push_str(&format!("data: {} words\n", words));
+        for (start, count) in &program.data {
+            map.push_str(&format!("  {:>5}-{}  {} words\n", start, start + count - 1, count));
+        }
+    }
+    map
+}
+
+#[test]
+fn test_memory_map() {
+    let program = crate::assemble("@i\nM=1\n@sum\nM=0\n").unwrap();
+    let expected = "instructions: 4\n\
+        ROM: 4 of 32768 words (0.0%)\n\
+        variables: 2\n\
+        \x20    16  i\n\
+        \x20    17  sum\n";
+    assert_eq!(expected, memory_map(&program));
+
+    // data ranges are listed after the variables they are kept apart from
+    let program = crate::assemble(".data 16\n.word 1, 2\n.data SCREEN\n.string \"A\"\n@i\n").unwrap();
+    let expected = "instructions: 19\n\
+        ROM: 19 of 32768 words (0.1%)\n\
+        variables: 1\n\
+        \x20    18  i\n\
+        data: 4 words\n\
+        \x20    16-17  2 words\n\
+        \x20 16384-16385  2 words\n";
+    assert_eq!(expected, memory_map(&program));
+}
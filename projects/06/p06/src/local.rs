@@ -0,0 +1,157 @@
+// Local labels, which belong to the global label before them, and
+// numbered anonymous labels
+// Author: Leo Robinovitch
+//
+// (MULT)
+// (.loop)        // MULT.loop
+// @.loop         // MULT.loop
+// (1)
+// @1b            // the nearest (1) before this line
+// @1f            // the nearest (1) after this line
+//
+// Anonymous labels are named `_1#0`, `_1#1`, ... in order, so like other
+// symbols starting with LOCAL_PREFIX they are private to their file. They
+// are left out of symbol maps and object files.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::{ErrorKind, SourceLine, LOCAL_PREFIX};
+
+/// Local labels start with this prefix
+pub const LOCAL_LABEL_PREFIX: char = '.';
+
+
+/// Checks if a label is anonymous: a number, e.g. `(1)`
+///
+/// # Arguments
+///
+/// * `label` - the label
+pub fn is_anonymous(label: &str) -> bool {
+    !label.is_empty() && label.bytes().all(|b| b.is_ascii_digit())
+}
+
+
+/// Splits a reference to an anonymous label, e.g. `1f`, into the label
+/// and whether it refers forward
+///
+/// # Arguments
+///
+/// * `symbol` - the symbol
+fn anonymous_reference(symbol: &str) -> Option<(&str, bool)> {
+    let (label, direction) = symbol.split_at(symbol.len().saturating_sub(1));
+    match direction {
+        "f" if is_anonymous(label) => Some((label, true)),
+        "b" if is_anonymous(label) => Some((label, false)),
+        _ => None
+    }
+}
+
+/// Checks if a symbol refers to a local or anonymous label, so is never a
+/// variable or a label of another file
+///
+/// # Arguments
+///
+/// * `symbol` - the symbol
+pub fn is_scoped(symbol: &str) -> bool {
+    symbol.starts_with(LOCAL_LABEL_PREFIX) || anonymous_reference(symbol).is_some()
+}
+
+#[test]
+fn test_is_scoped() {
+    assert!(is_anonymous("1"));
+    assert!(!is_anonymous("1f"));
+    assert_eq!(Some(("12", true)), anonymous_reference("12f"));
+    assert_eq!(Some(("1", false)), anonymous_reference("1b"));
+    assert_eq!(None, anonymous_reference("xb"));
+    assert_eq!(None, anonymous_reference("b"));
+    assert!(is_scoped(".loop"));
+    assert!(is_scoped("1f"));
+    assert!(!is_scoped("MULT.loop"));
+}
+
+
+/// Tracks the global label and anonymous labels before each line, to
+/// name local and anonymous labels. Lines must be visited in order, with
+/// `declare` called at each label.
+#[derive(Default)]
+pub(crate) struct Scope<'a> {
+    /// the last global label of each file
+    globals: HashMap<&'a str, &'a str>,
+    /// anonymous labels declared so far in each file, by label
+    anonymous: HashMap<(&'a str, &'a str), usize>,
+}
+
+impl<'a> Scope<'a> {
+    /// Returns the name of a label declared at a line, and moves past it
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - the source line
+    /// * `label` - the label as written
+    pub fn declare(&mut self, line: &'a SourceLine, label: &'a str) -> Result<Cow<'a, str>, ErrorKind> {
+        if is_anonymous(label) {
+            let count = self.anonymous.entry((&line.file, label)).or_insert(0);
+            *count += 1;
+            Ok(Cow::Owned(format!("{}{}#{}", LOCAL_PREFIX, label, *count - 1)))
+        } else if label.starts_with(LOCAL_LABEL_PREFIX) {
+            self.resolve(line, label)
+        } else {
+            self.globals.insert(&line.file, label);
+            Ok(Cow::Borrowed(label))
+        }
+    }
+
+    /// Returns the name of the label a symbol used at a line refers to.
+    /// Symbols that are not local or anonymous labels are returned as is.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - the source line
+    /// * `symbol` - the symbol as written
+    pub fn resolve<'s>(&self, line: &SourceLine, symbol: &'s str) -> Result<Cow<'s, str>, ErrorKind> {
+        if symbol.starts_with(LOCAL_LABEL_PREFIX) {
+            return match self.globals.get(&*line.file) {
+                Some(global) => Ok(Cow::Owned(format!("{}{}", global, symbol))),
+                None => Err(ErrorKind::LocalWithoutGlobal)
+            };
+        }
+        match anonymous_reference(symbol) {
+            Some((label, forward)) => {
+                let count = self.anonymous.get(&(&*line.file, label)).copied().unwrap_or(0);
+                match (forward, count) {
+                    (true, _) => Ok(Cow::Owned(format!("{}{}#{}", LOCAL_PREFIX, label, count))),
+                    (false, 0) => Err(ErrorKind::UndefinedLabel),
+                    (false, _) => Ok(Cow::Owned(format!("{}{}#{}", LOCAL_PREFIX, label, count - 1)))
+                }
+            },
+            None => Ok(Cow::Borrowed(symbol))
+        }
+    }
+}
+
+#[test]
+fn test_scope() {
+    let line = |file: &str| SourceLine { file: file.into(), number: 1, text: "".into() };
+    let (main, other) = (line("main.asm"), line("other.asm"));
+    let mut scope = Scope::default();
+
+    assert_eq!(Err(ErrorKind::LocalWithoutGlobal), scope.declare(&main, ".loop"));
+    assert_eq!(Err(ErrorKind::UndefinedLabel), scope.resolve(&main, "1b"));
+    assert_eq!("_1#0", scope.resolve(&main, "1f").unwrap());
+    assert_eq!("_1#0", scope.declare(&main, "1").unwrap());
+    assert_eq!("_1#0", scope.resolve(&main, "1b").unwrap());
+    assert_eq!("_1#1", scope.resolve(&main, "1f").unwrap());
+
+    assert_eq!("MULT", scope.declare(&main, "MULT").unwrap());
+    assert_eq!("MULT.loop", scope.declare(&main, ".loop").unwrap());
+    assert_eq!("MULT.end", scope.resolve(&main, ".end").unwrap());
+    assert_eq!("DIV", scope.declare(&main, "DIV").unwrap());
+    assert_eq!("DIV.loop", scope.resolve(&main, ".loop").unwrap());
+    assert_eq!("R0", scope.resolve(&main, "R0").unwrap());
+
+    // each file has its own scope
+    assert_eq!(Err(ErrorKind::LocalWithoutGlobal), scope.resolve(&other, ".loop"));
+    assert_eq!("_1#0", scope.resolve(&other, "1f").unwrap());
+    assert_eq!("DIV.loop", scope.resolve(&main, ".loop").unwrap());
+}
@@ -0,0 +1,360 @@
+// Macro expansion for Hack assembly, run before the first (label) pass
+// Author: Leo Robinovitch
+//
+// .macro POP dest
+//     @SP
+//     AM=M-1
+//     D=M
+//     @dest
+//     M=D
+// .endm
+//
+// POP R13
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::rc::Rc;
+
+use regex::Regex;
+
+use crate::{dest_map, jump_map, remove_comments, AssembleError, ErrorKind, LineError, SourceLine};
+use crate::local::LOCAL_LABEL_PREFIX;
+use crate::parser::is_symbol_char;
+
+/// Deepest allowed nesting of macro invocations
+const MAX_DEPTH: usize = 16;
+
+/// A macro definition
+struct Macro {
+    params: Vec<String>,
+    body: Vec<SourceLine>,
+    /// labels declared in the body, made unique for each expansion
+    labels: Vec<String>,
+}
+
+
+/// Returns text with every whole symbol found in `replacements` replaced,
+/// so that replacing `i` leaves `@index` alone
+///
+/// # Arguments
+///
+/// * `text` - line to replace symbols in
+/// * `replacements` - map of symbols to their replacements
+pub fn replace_symbols(text: &str, replacements: &HashMap<String, String>) -> String {
+    let mut replaced = String::new();
+    let mut symbol = String::new();
+    for c in text.chars().chain(std::iter::once('\n')) {
+        if is_symbol_char(c) {
+            symbol.push(c);
+            continue;
+        }
+        match replacements.get(&symbol) {
+            Some(replacement) => replaced.push_str(replacement),
+            None => replaced.push_str(&symbol)
+        }
+        symbol.clear();
+        replaced.push(c);
+    }
+    replaced.pop(); // the added newline
+    replaced
+}
+
+#[test]
+fn test_replace_symbols() {
+    let mut replacements: HashMap<String, String> = HashMap::new();
+    replacements.insert("i".to_string(), "R13".to_string());
+    replacements.insert("LOOP".to_string(), "INC$1$LOOP".to_string());
+    assert_eq!("@R13 // @index", replace_symbols("@i // @index", &replacements));
+    assert_eq!("(INC$1$LOOP)", replace_symbols("(LOOP)", &replacements));
+    assert_eq!("M=M+1", replace_symbols("M=M+1", &replacements));
+}
+
+
+/// Returns whether name is a register or mnemonic, which can't name a macro
+/// because a line like `A` or `JMP` would be ambiguous
+///
+/// # Arguments
+///
+/// * `name` - name of the macro
+fn is_mnemonic(name: &str) -> bool {
+    dest_map().contains_key(name) || jump_map().contains_key(name)
+}
+
+#[test]
+fn test_is_mnemonic() {
+    assert!(is_mnemonic("A"));
+    assert!(is_mnemonic("AMD"));
+    assert!(is_mnemonic("JMP"));
+    assert!(!is_mnemonic("SET"));
+    assert!(!is_mnemonic("a"));
+}
+
+
+/// Splits the source lines into macro definitions and the remaining lines
+///
+/// # Arguments
+///
+/// * `source_lines` - lines of the .asm sources
+/// * `errors` - errors found are pushed here
+fn collect_definitions(source_lines: Vec<SourceLine>, errors: &mut Vec<AssembleError>)
+    -> (HashMap<String, Macro>, Vec<SourceLine>) {
+    lazy_static! { // lazy_static ensures compilation only happens once
+        static ref RE : Regex = Regex::new(
+                r"^\.macro\s+([a-zA-Z_][a-zA-Z0-9_\.$:]*)((\s+[a-zA-Z_][a-zA-Z0-9_\.$:]*)*)\s*$"
+            ).unwrap();
+        static ref LABEL_RE : Regex = Regex::new(
+                r"^\(([^\d][a-zA-Z0-9_\.$:]*)\)$"
+            ).unwrap();
+    };
+
+    let mut macros: HashMap<String, Macro> = HashMap::new();
+    let mut lines: Vec<SourceLine> = Vec::new();
+    let mut definition: Option<(String, SourceLine, Macro)> = None;
+
+    for line in source_lines {
+        // outside a definition, only directives need their comments removed
+        if definition.is_none() && !line.text.trim_start().starts_with('.') {
+            lines.push(line);
+            continue;
+        }
+        let stripped_line = remove_comments(&line.text);
+
+        if stripped_line.starts_with(".macro") {
+            if definition.is_some() {
+                let error = LineError::new(ErrorKind::NestedMacro, stripped_line, stripped_line);
+                errors.push(error.locate(&line, stripped_line));
+                continue;
+            }
+            match RE.captures(stripped_line) {
+                Some(capture) => {
+                    let name = capture.get(1).unwrap().as_str();
+                    if is_mnemonic(name) {
+                        // the body is still read so its lines and .endm aren't misreported
+                        let error = LineError::new(ErrorKind::InvalidMacro, stripped_line, name);
+                        errors.push(error.locate(&line, stripped_line));
+                    }
+                    let name = name.to_string();
+                    let params = capture.get(2).unwrap().as_str()
+                        .split_whitespace()
+                        .map(|param| param.to_string())
+                        .collect();
+                    let mac = Macro { params, body: Vec::new(), labels: Vec::new() };
+                    definition = Some((name, line, mac));
+                },
+                None => {
+                    let error = LineError::new(ErrorKind::InvalidMacro, stripped_line, stripped_line);
+                    errors.push(error.locate(&line, stripped_line));
+                }
+            }
+        } else if stripped_line == ".endm" {
+            match definition.take() {
+                Some((name, _, _)) if is_mnemonic(&name) => (),
+                Some((name, start, mac)) => {
+                    match macros.entry(name) {
+                        Entry::Occupied(_) => {
+                            let stripped_start = remove_comments(&start.text);
+                            let error = LineError::new(ErrorKind::DuplicateMacro, stripped_start, stripped_start);
+                            errors.push(error.locate(&start, stripped_start));
+                        },
+                        Entry::Vacant(entry) => {
+                            entry.insert(mac);
+                        }
+                    }
+                },
+                None => {
+                    let error = LineError::new(ErrorKind::UnmatchedEndm, stripped_line, stripped_line);
+                    errors.push(error.locate(&line, stripped_line));
+                }
+            }
+        } else if let Some((_, _, mac)) = definition.as_mut() {
+            if let Some(capture) = LABEL_RE.captures(stripped_line) {
+                mac.labels.push(capture.get(1).unwrap().as_str().to_string());
+            }
+            mac.body.push(line);
+        } else {
+            lines.push(line);
+        }
+    }
+
+    if let Some((_, start, _)) = definition {
+        let stripped_start = remove_comments(&start.text);
+        let error = LineError::new(ErrorKind::UnterminatedMacro, stripped_start, stripped_start);
+        errors.push(error.locate(&start, stripped_start));
+    }
+
+    (macros, lines)
+}
+
+
+/// Expands macro invocations in a line, pushing the resulting lines to
+/// `expanded`. Expanded lines keep the line number of the invocation.
+///
+/// # Arguments
+///
+/// * `line` - line that may be a macro invocation
+/// * `macros` - macro definitions
+/// * `depth` - how many invocations deep this line is
+/// * `expansion_count` - count of previous expansions, to make labels unique
+/// * `expanded` - expanded lines are pushed here
+/// * `errors` - errors found are pushed here
+fn expand_line(
+    line: SourceLine,
+    macros: &HashMap<String, Macro>,
+    depth: usize,
+    expansion_count: &mut usize,
+    expanded: &mut Vec<SourceLine>,
+    errors: &mut Vec<AssembleError>) {
+
+    let stripped_line = remove_comments(&line.text);
+    let name = stripped_line.split_whitespace().next().unwrap_or("");
+    let mac = match macros.get(name) {
+        Some(mac) => mac,
+        None => {
+            expanded.push(line);
+            return;
+        }
+    };
+
+    if depth >= MAX_DEPTH {
+        let error = LineError::new(ErrorKind::RecursiveMacro, stripped_line, name);
+        errors.push(error.locate(&line, stripped_line));
+        return;
+    }
+
+    let args: Vec<&str> = stripped_line[name.len()..]
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|arg| !arg.is_empty())
+        .collect();
+    if args.len() != mac.params.len() {
+        let error = LineError::new(ErrorKind::MacroArguments, stripped_line, stripped_line);
+        errors.push(error.locate(&line, stripped_line));
+        return;
+    }
+
+    *expansion_count += 1;
+    let mut replacements: HashMap<String, String> = HashMap::new();
+    for label in &mac.labels {
+        // local labels stay local, so the expansion keeps the caller's global label
+        let unique = match label.strip_prefix(LOCAL_LABEL_PREFIX) {
+            Some(local) => format!("{}{}${}${}", LOCAL_LABEL_PREFIX, name, expansion_count, local),
+            None => format!("{}${}${}", name, expansion_count, label)
+        };
+        replacements.insert(label.clone(), unique);
+    }
+    for (param, arg) in mac.params.iter().zip(args) {
+        replacements.insert(param.clone(), arg.to_string());
+    }
+
+    for body_line in &mac.body {
+        let body_line = SourceLine {
+            file: line.file.clone(),
+            number: line.number,
+            text: Rc::from(replace_symbols(&body_line.text, &replacements)),
+        };
+        expand_line(body_line, macros, depth + 1, expansion_count, expanded, errors);
+    }
+}
+
+
+/// Returns the source lines with macro definitions removed and macro
+/// invocations expanded. Macros are shared between files.
+///
+/// # Arguments
+///
+/// * `source_lines` - lines of the .asm sources
+/// * `errors` - errors found are pushed here
+pub fn expand_macros(source_lines: Vec<SourceLine>, errors: &mut Vec<AssembleError>) -> Vec<SourceLine> {
+    let (macros, lines) = collect_definitions(source_lines, errors);
+    if macros.is_empty() {
+        return lines; // nothing to expand
+    }
+
+    let mut expanded: Vec<SourceLine> = Vec::new();
+    let mut expansion_count = 0;
+    for line in lines {
+        expand_line(line, &macros, 0, &mut expansion_count, &mut expanded, errors);
+    }
+    expanded
+}
+
+/// Returns the lines of a test source
+#[cfg(test)]
+fn source_lines(source: &str) -> Vec<SourceLine> {
+    let file: Rc<str> = Rc::from("test.asm");
+    source.lines().enumerate()
+        .map(|(idx, text)| SourceLine { file: file.clone(), number: idx + 1, text: Rc::from(text) })
+        .collect()
+}
+
+#[test]
+fn test_expand_macros() {
+    let source = ".macro POP dest\n\
+        @SP\n\
+        AM=M-1\n\
+        D=M\n\
+        @dest\n\
+        M=D\n\
+        .endm\n\
+        POP R13\n\
+        POP R14 // again\n";
+    let mut errors: Vec<AssembleError> = Vec::new();
+    let lines = expand_macros(source_lines(source), &mut errors);
+    assert!(errors.is_empty());
+    let texts: Vec<&str> = lines.iter().map(|line| &*line.text).collect();
+    assert_eq!(vec!["@SP", "AM=M-1", "D=M", "@R13", "M=D", "@SP", "AM=M-1", "D=M", "@R14", "M=D"], texts);
+    assert_eq!(vec![8, 8, 8, 8, 8, 9, 9, 9, 9, 9], lines.iter().map(|line| line.number).collect::<Vec<usize>>());
+}
+
+#[test]
+fn test_expand_macros_labels() {
+    let source = ".macro WAIT\n\
+        (LOOP)\n\
+        @LOOP\n\
+        0;JMP\n\
+        .endm\n\
+        .macro TWICE\n\
+        WAIT\n\
+        WAIT\n\
+        .endm\n\
+        TWICE\n";
+    let mut errors: Vec<AssembleError> = Vec::new();
+    let lines = expand_macros(source_lines(source), &mut errors);
+    assert!(errors.is_empty());
+    let texts: Vec<&str> = lines.iter().map(|line| &*line.text).collect();
+    assert_eq!(vec!["(WAIT$2$LOOP)", "@WAIT$2$LOOP", "0;JMP", "(WAIT$3$LOOP)", "@WAIT$3$LOOP", "0;JMP"], texts);
+
+    let source = ".macro SKIP\n(.skip)\n@.skip\n.endm\nSKIP\n";
+    let lines = expand_macros(source_lines(source), &mut errors);
+    let texts: Vec<&str> = lines.iter().map(|line| &*line.text).collect();
+    assert_eq!(vec!["(.SKIP$1$skip)", "@.SKIP$1$skip"], texts);
+}
+
+#[test]
+fn test_expand_macros_errors() {
+    let source = ".macro SET x\n\
+        @x\n\
+        .endm\n\
+        SET\n\
+        .endm\n\
+        .macro B\n\
+        B\n\
+        .endm\n\
+        B\n\
+        .macro C\n";
+    let mut errors: Vec<AssembleError> = Vec::new();
+    expand_macros(source_lines(source), &mut errors);
+    let found: Vec<(ErrorKind, usize)> = errors.iter().map(|e| (e.kind, e.line)).collect();
+    assert_eq!(vec![
+        (ErrorKind::UnmatchedEndm, 5),
+        (ErrorKind::UnterminatedMacro, 10),
+        (ErrorKind::MacroArguments, 4),
+        (ErrorKind::RecursiveMacro, 9),
+    ], found);
+
+    // registers and mnemonics can't name macros
+    let source = ".macro A x\n@x\n.endm\n.macro AM\n.endm\n.macro JMP\n.endm\n";
+    let mut errors: Vec<AssembleError> = Vec::new();
+    expand_macros(source_lines(source), &mut errors);
+    let found: Vec<(ErrorKind, usize)> = errors.iter().map(|e| (e.kind, e.line)).collect();
+    assert_eq!(vec![(ErrorKind::InvalidMacro, 1), (ErrorKind::InvalidMacro, 4), (ErrorKind::InvalidMacro, 6)], found);
+}
@@ -1,498 +1,468 @@
 // Assembler (Mnemonics -> Machine Code) for the Nand2Tetris Hack Computer
 // Author: Leo Robinovitch
 
-use std::fs::File;
-use std::path::Path;
-use std::io::BufReader;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::io::{self, BufReader, BufWriter};
 use std::io::prelude::*;
 use std::env;
-use std::collections::HashMap;
-
-use regex::Regex;
-#[macro_use]
-extern crate lazy_static;
-
-// create an enum type called CommandType
-// that implements the Debug, etc. traits
-//     (printing with {:?} tells type)
-#[derive(PartialEq, Eq, Debug)]
-enum CommandType {
-    A,
-    L,
-    C
-}
+use std::process;
+
+use p06::{assemble_object, assemble_sources, Options as AssembleOptions, Program, Source};
+use p06::diagnostic;
+use p06::disassemble::disassemble;
+use p06::link::link;
+use p06::listing;
+use p06::object::{Object, OBJECT_EXTENSION};
+use p06::output::{self, OutputFormat};
+
+/// Path meaning stdin as an input and stdout as an output
+const STDIO: &str = "-";
+
+/// Name of the source read from stdin, used in diagnostics
+const STDIN_NAME: &str = "<stdin>";
 
-/// Returns a String of the file contents at path
+/// Returns a String of the file contents at path, or of stdin for `-`
 /// Note: path is referenced from the root directory of the project
-/// 
+///
 /// # Arguments
-/// 
+///
 /// * `path` - A std::path::Path that contains the input file path
 /// * `extension` - required extension for file
-fn get_file_contents(path: &Path, extension: &str) -> String { // takes reference to str, "read only"
+fn get_file_contents(path: &Path, extension: &str) -> io::Result<String> {
 
-    assert_eq!(path.extension().unwrap(), extension);
+    let mut contents = String::new();
+    if path == Path::new(STDIO) {
+        io::stdin().read_to_string(&mut contents)?;
+        return Ok(contents);
+    }
+
+    if path.extension().is_none_or(|ext| ext != extension) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("expected a .{} file", extension)));
+    }
 
-    let file = File::open(path).expect("Failed to open file");
+    let file = File::open(path)?;
     let mut buf_reader = BufReader::new(file); // buffer reader for file
-    let mut contents = String::new(); // mutable string variable for contents
-    buf_reader.read_to_string(&mut contents) // mutable reference to contents
-        .expect("Error reading to string");
-    contents // returns contents
+    buf_reader.read_to_string(&mut contents)?; // mutable reference to contents
+    Ok(contents)
 }
 
 #[test]
 fn test_get_file_contents() {
-    // no test implemented
+    let error = get_file_contents(Path::new("--help"), "asm").unwrap_err();
+    assert_eq!("expected a .asm file", error.to_string());
+    let error = get_file_contents(Path::new("missing/Add.asm"), "asm").unwrap_err();
+    assert_eq!(io::ErrorKind::NotFound, error.kind());
 }
 
 
-/// Returns a str after removing comments and white space
-/// 
+/// Returns the file contents at path like `get_file_contents`, printing
+/// the error and exiting if it can't be read
+///
 /// # Arguments
-/// 
-/// * `line` - the current line
-fn remove_comments(line: &str) -> &str {
-    
-    // find the index where comments begin on the line
-    let idx_comment = match line.find("//") {
-        Some(idx) => idx,
-        _ => line.len()
-    };
-
-    // return a reference to the reduced str with no start/end whitespace
-    // note that memory contents are the same, just pointer and/or len changed
-    &line[0..idx_comment].trim()
-}
-
-#[test]
-fn test_stripped_line() {
-    assert_eq!("", remove_comments(""));
-    assert_eq!("", remove_comments("    "));
-    assert_eq!("", remove_comments("//   "));
-    assert_eq!("nand2tetris", remove_comments("nand2tetris   // is so cool"));
-    assert_eq!("nand2tetris is so cool", remove_comments("nand2tetris is so cool // eh?"));
+///
+/// * `path` - input path, `-` for stdin
+/// * `extension` - required extension for file
+fn read_or_exit(path: &str, extension: &str) -> String {
+    get_file_contents(Path::new(path), extension).unwrap_or_else(|error| {
+        eprintln!("error: could not read {:?}: {}", source_name(path), error);
+        process::exit(1);
+    })
 }
 
 
-/// Returns CommandType of input line
-/// 
+/// Create and return buffered writable file based on path, or stdout for `-`
+///
 /// # Arguments
-/// 
-/// * `line` - a string slice that holds the current line
-fn get_command_type(line: &str) -> CommandType {
-    if line.contains('@') {
-        return CommandType::A;
-    } else if line.contains('(') && line.contains(')') {
-        return CommandType::L;
+///
+/// * `path`
+fn create_file(path: &Path) -> io::Result<Box<dyn Write>> {
+    if path == Path::new(STDIO) {
+        Ok(Box::new(BufWriter::new(io::stdout())))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
     }
-    CommandType::C
-}
-
-#[test]
-fn test_get_command_type() {
-    assert_eq!(CommandType::A, get_command_type("@test"));
-    assert_eq!(CommandType::L, get_command_type("(test)"));
-    assert_eq!(CommandType::C, get_command_type("A=1;JEQ"));
-    assert_eq!(CommandType::C, get_command_type("A=M"));
-    assert_eq!(CommandType::C, get_command_type("0;JMP"));
 }
 
 
-/// Get symbol represented in A command, e.g. `@SYMBOL`
-/// 
+/// Write bytes to file and flush it
+///
 /// # Arguments
-/// 
-/// * `command` - a string slice that holds the A command with the symbol
-fn get_symbol_a_command(command: &str) -> &str {
-    lazy_static! { // lazy_static ensures compilation only happens once
-        static ref RE : Regex = Regex::new(
-                r"^@([^\d][a-zA-Z0-9_\.$:]*)\s*(\S*)"
-            ).unwrap();
-    };
-
-    let capture = RE.captures(command)
-        .expect("Invalid symbol");
-
-    // ensure the A_Command has no garbage after it 
-    assert_eq!("", capture.get(2).unwrap().as_str(), "Invalid A Command: {}", command);
-
-    capture.get(1).unwrap().as_str()
-}
-
-#[test]
-fn test_get_symbol_a_command() {
-    assert_eq!("test", get_symbol_a_command("@test"));
-    assert_eq!("Test_:123$", get_symbol_a_command("@Test_:123$"));
-    let result = std::panic::catch_unwind(|| get_symbol_a_command("@Test_:123$%")); // % is invalid
-    assert!(result.is_err());
-    let result = std::panic::catch_unwind(|| get_symbol_a_command("@test test"));
-    assert!(result.is_err());
-    let result = std::panic::catch_unwind(|| get_symbol_a_command("@1test"));
-    assert!(result.is_err());
+///
+/// * `file` - buffered writable file
+/// * `bytes` - bytes to write to file
+fn write_to_file(file: &mut dyn Write, bytes: &[u8]) -> io::Result<()> {
+    file.write_all(bytes)?;
+    file.flush()
 }
 
 
-/// Checks if the A command contains a symbol (returns true) or 
-/// refers to a number directly (returns false)
-/// 
+/// Create the file at path and write to it with `write`, printing the
+/// error and exiting if it can't be written
+///
 /// # Arguments
-/// 
-/// * `stripped_line` - input line free of comments and whitespace
-fn a_command_contains_symbol(stripped_line: &str) -> bool {
-    lazy_static! { // lazy_static ensures compilation only happens once
-        static ref RE : Regex = Regex::new(
-                r"^@([^\d][a-zA-Z0-9_\.$:]*)\s*(\S*)"
-            ).unwrap();
-    };
-
-    match RE.captures(stripped_line) {
-        Some(_) => true,
-        _ => false
+///
+/// * `path` - output path, `-` for stdout
+/// * `write` - writes the contents to the file
+fn write_or_exit(path: &Path, write: impl FnOnce(&mut dyn Write) -> io::Result<()>) {
+    let result = create_file(path).and_then(|mut file| write(&mut file));
+    if let Err(error) = result {
+        eprintln!("error: could not write {:?}: {}", path, error);
+        process::exit(1);
     }
 }
 
-#[test]
-fn test_a_command_contains_symbol() {
-    assert_eq!(true, a_command_contains_symbol("@test"));
-    assert_eq!(false, a_command_contains_symbol("@1"));
+
+/// Command line options
+struct Options {
+    in_paths: Vec<String>,
+    /// output path from --output, `-` for stdout
+    out_path: Option<String>,
+    format: OutputFormat,
+    listing: bool,
+    symbols: bool,
+    optimize: bool,
+    summary: bool,
+    object: bool,
+    check: bool,
 }
 
 
-/// Get symbol represented in L command, e.g. `(SYMBOL)`
-/// 
-/// # Arguments
-/// 
-/// * `command` - a string slice that holds the L command with the symbol
-fn get_symbol_l_command(command: &str) -> &str {
-    lazy_static! { // lazy_static ensures compilation only happens once
-        static ref RE : Regex = Regex::new(
-                r"^\(([^\d][a-zA-Z0-9_\.$:]*)\)\s*(\S*)"
-            ).unwrap();
-    };
+/// Print usage and exit
+fn usage() -> ! {
+    println!("Usage: cargo run [--output PATH] [--format FORMAT] [--listing] [--symbols] [--optimize] [--summary] [--object] [--check] FILENAME...");
+    println!("       FILENAME.asm is assembled to FILENAME.hack");
+    println!("       - as FILENAME reads stdin and writes stdout, unless --output is given");
+    println!("       several .asm files are assembled together, named after the first");
+    println!("       FILENAME.hack is disassembled and printed");
+    println!("       several .hobj object files are linked together, named after the first");
+    println!("       --output writes to PATH instead, or to stdout for -");
+    println!("       FORMAT is one of hack (default), bin-be, bin-le, ihex, logisim");
+    println!("       --listing also writes FILENAME.lst, --symbols also writes FILENAME.sym");
+    println!("       --optimize removes redundant and unreachable instructions");
+    println!("       --summary prints ROM utilization and variable addresses");
+    println!("       --object assembles each .asm file to a FILENAME.hobj object file to link later");
+    println!("       --check only reports errors and warnings, writing no files");
+    process::exit(2);
+}
 
-    let capture = RE.captures(command)
-        .expect("Invalid symbol");
 
-    // ensure the L_Command has no garbage after it 
-    assert_eq!("", capture.get(2).unwrap().as_str(), "Invalid L Command: {}", command);
+/// Parse command line arguments and return options
+fn parse_args() -> Options {
+    // get user args
+    let args: Vec<String> = env::args().collect();
+
+    let mut in_paths: Vec<String> = Vec::new();
+    let mut out_path: Option<String> = None;
+    let mut format = OutputFormat::Hack;
+    let mut listing = false;
+    let mut symbols = false;
+    let mut optimize = false;
+    let mut summary = false;
+    let mut object = false;
+    let mut check = false;
+    let mut idx = 1;
+    while idx < args.len() {
+        match args[idx].as_str() {
+            "--output" | "-o" => {
+                idx += 1;
+                out_path = match args.get(idx) {
+                    Some(path) => Some(path.to_string()),
+                    None => {
+                        println!("Missing output path!");
+                        usage();
+                    }
+                };
+            },
+            "--format" | "-f" => {
+                idx += 1;
+                format = match args.get(idx).and_then(|name| OutputFormat::from_name(name)) {
+                    Some(format) => format,
+                    None => {
+                        println!("Invalid or missing output format!");
+                        usage();
+                    }
+                };
+            },
+            "--listing" | "-l" => listing = true,
+            "--symbols" | "-s" => symbols = true,
+            "--optimize" | "-O" => optimize = true,
+            "--summary" | "-m" => summary = true,
+            "--object" | "-c" => object = true,
+            "--check" => check = true,
+            _ => in_paths.push(args[idx].to_string())
+        }
+        idx += 1;
+    }
 
-    capture.get(1).unwrap().as_str()
+    // check user args
+    if in_paths.is_empty() {
+        println!("Missing required argument!");
+        usage();
+    }
+    if object && out_path.is_some() && in_paths.len() > 1 {
+        println!("--output with --object needs a single input file!");
+        usage();
+    }
+    let named = |path: &Option<String>| path.as_ref().is_some_and(|path| path != STDIO);
+    if (listing || symbols) && in_paths[0] == STDIO && !named(&out_path) {
+        println!("--listing and --symbols need an input or output file name!");
+        usage();
+    }
+    Options { in_paths, out_path, format, listing, symbols, optimize, summary, object, check }
 }
 
 #[test]
-fn test_get_symbol_l_command() {
-    assert_eq!("test", get_symbol_l_command("(test)"));
-    assert_eq!("Test_:123$", get_symbol_l_command("(Test_:123$)"));
-    let result = std::panic::catch_unwind(|| get_symbol_l_command("(Test_:123$%)")); // % is invalid
-    assert!(result.is_err());
-    let result = std::panic::catch_unwind(|| get_symbol_l_command("(test) test"));
-    assert!(result.is_err());
-    let result = std::panic::catch_unwind(|| get_symbol_l_command("(1test)"));
-    assert!(result.is_err());
+fn test_parse_args() {
+    // not implemented
 }
 
 
-/// Get (dest, comp, jump) mnemonics contained in complete command
-/// 
+/// Returns the name of the source at path, used in diagnostics
+///
 /// # Arguments
-/// 
-/// * `command` - a string slice that holds the C command with the mnemonics
-fn get_c_command_mnemonics(command: &str) -> (&str, &str, &str) {
-    lazy_static! { // lazy_static ensures compilation only happens once
-        static ref RE : Regex = Regex::new(
-                r"^([MDA]?[MD]?[D]?)\s*=?\s*([01\-DA!M][1DA+\-&|M]?[1ADM]?\s*);?\s*(J?[GELNM]?[TQEP]?)"
-            ).unwrap();
-    };
+///
+/// * `path` - input path, `-` for stdin
+fn source_name(path: &str) -> String {
+    if path == STDIO { STDIN_NAME.to_string() } else { path.to_string() }
+}
 
-    let capture = RE.captures(command)
-        .expect("Invalid symbol");
 
-    (
-        capture.get(1).unwrap().as_str(), // dest
-        capture.get(2).unwrap().as_str(), // comp
-        capture.get(3).unwrap().as_str(), // jump
-    )
+/// Returns the path to write output with the given extension to: the
+/// --output path if given, else stdout when reading stdin, else next to
+/// the input
+///
+/// # Arguments
+///
+/// * `options` - command line options
+/// * `in_path` - path of the (first) input file
+/// * `extension` - extension of the output file
+fn out_path(options: &Options, in_path: &str, extension: &str) -> PathBuf {
+    match &options.out_path {
+        Some(out_path) => PathBuf::from(out_path),
+        None if in_path == STDIO => PathBuf::from(STDIO),
+        None => Path::new(in_path).with_extension(extension),
+    }
 }
 
+
 #[test]
-fn test_get_c_command_mnemonics() {
-    // dest, comp, jump  --- dest=comp;jump
-    assert_eq!(("M", "D", "JMP"), get_c_command_mnemonics("M=D;JMP"));
-    assert_eq!(("", "D", "JGT"), get_c_command_mnemonics("D;JGT"));
-    assert_eq!(("", "D", ""), get_c_command_mnemonics("D"));
-    assert_eq!(("M", "D", ""), get_c_command_mnemonics("M=D"));
-    assert_eq!(("M", "D+M", ""), get_c_command_mnemonics("M=D+M"));
-    assert_eq!(("M", "M+1", ""), get_c_command_mnemonics("M=M+1"));
-    assert_eq!(("", "0", "JMP"), get_c_command_mnemonics("0;JMP"));
+fn test_out_path() {
+    let mut options = Options {
+        in_paths: vec![], out_path: None, format: OutputFormat::Hack, listing: false,
+        symbols: false, optimize: false, summary: false, object: false, check: false,
+    };
+    assert_eq!(PathBuf::from("add/Add.hack"), out_path(&options, "add/Add.asm", "hack"));
+    assert_eq!(PathBuf::from("-"), out_path(&options, "-", "hack"));
+    options.out_path = Some("out/add.bin".to_string());
+    assert_eq!(PathBuf::from("out/add.bin"), out_path(&options, "-", "hack"));
+    assert_eq!(PathBuf::from("out/add.lst"), side_path(&options, "add/Add.asm", "lst"));
+    options.out_path = Some("-".to_string());
+    assert_eq!(PathBuf::from("add/Add.lst"), side_path(&options, "add/Add.asm", "lst"));
 }
 
 
-/// Get 16 bit text representation of A command
-/// 
+/// Returns the path of a file written alongside the output, e.g. the
+/// listing, named after the output file if it is one, else the input
+///
 /// # Arguments
-/// 
-/// * `command` - a string slice that holds the A command
-fn get_a_bits(command: &str) -> String {
-    match command.replace("@", "").parse::<i32>() {
-        Ok(num) => format!("{:#018b}", num).to_string().replace("0b", ""),
-        Err(e) => {
-            panic!("Problem parsing number {:?} to string from line {:?}", e, command)
-        },
+///
+/// * `options` - command line options
+/// * `in_path` - path of the (first) input file
+/// * `extension` - extension of the file
+fn side_path(options: &Options, in_path: &str, extension: &str) -> PathBuf {
+    match &options.out_path {
+        Some(out_path) if out_path != STDIO => Path::new(out_path).with_extension(extension),
+        _ => Path::new(in_path).with_extension(extension),
     }
 }
 
-#[test]
-fn test_get_a_bits() {
-    assert_eq!("0000000000000010", get_a_bits("@2"));
-    assert_eq!("0000000000000001", get_a_bits("@1"));
+
+/// Print a status message, to stderr when the output goes to stdout
+///
+/// # Arguments
+///
+/// * `out_path` - where the output is written
+/// * `message` - the message
+fn print_status(out_path: &Path, message: &str) {
+    if out_path == Path::new(STDIO) {
+        eprint!("{}", message);
+    } else {
+        print!("{}", message);
+    }
 }
 
 
-/// Get 16 bit text representation of C command
-/// 
+/// Assemble .asm files to an output file next to the first one, printing
+/// diagnostics and exiting if the files have errors
+///
 /// # Arguments
-/// 
-/// * `command` - a string slice that holds the C command
-/// * `comp_map` - hashmap of comp mnemonics to bits
-/// * `dest_map` - hashmap of dest mnemonics to bits
-/// * `jump_map` - hashmap of jump mnemonics to bits
-fn get_c_bits(
-    command: &str,
-    comp_map: &HashMap<&str, String>,
-    dest_map: &HashMap<&str, String>,
-    jump_map: &HashMap<&str, String>) -> String {
-    
-    let (dest, comp, jump) = get_c_command_mnemonics(command);
-    let comp_bits = comp_map.get(&comp).expect("No mapping found for comp mnemonic");
-    let dest_bits = dest_map.get(&dest).expect("No mapping found for dest mnemonic");
-    let jump_bits = jump_map.get(&jump).expect("No mapping found for jump mnemonic");
-
-    let bits = "111".to_string();
-    bits + comp_bits + dest_bits + jump_bits
-}
+///
+/// * `options` - command line options
+fn assemble_files(options: &Options) {
+    let in_path = options.in_paths[0].as_str();
+    let sources: Vec<Source> = options.in_paths.iter()
+        .map(|path| Source { name: source_name(path), contents: read_or_exit(path, "asm") })
+        .collect();
+    let assemble_options = AssembleOptions { optimize: options.optimize };
+    let program = match assemble_sources(&sources, &|path| fs::read_to_string(path), &assemble_options) {
+        Ok(program) => program,
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("{}", diagnostic::render(error));
+            }
+            let plural = if errors.len() == 1 { "" } else { "s" };
+            eprintln!("error: could not assemble {:?} due to {} previous error{}",
+                sources[0].name, errors.len(), plural);
+            process::exit(1);
+        }
+    };
 
-#[test]
-fn test_get_c_bits() {
-    // not implemented
+    for warning in &program.warnings {
+        eprintln!("{}", diagnostic::render_warning(warning));
+    }
+
+    if !options.check {
+        write_program(&program, in_path, "Assembled", options);
+    }
 }
 
 
-/// Create and return writable file based on path
-/// 
+/// Write a program to the output file, along with the listing and symbol
+/// map if requested, and print the summary
+///
 /// # Arguments
-/// 
-/// * `path`
-fn create_file(path: &Path) -> File {
-    File::create(&path).unwrap()
+///
+/// * `program` - assembled or linked program
+/// * `in_path` - path of the first input file
+/// * `verb` - how the program was made, for the message
+/// * `options` - command line options
+fn write_program(program: &Program, in_path: &str, verb: &str, options: &Options) {
+    let out_path = out_path(options, in_path, options.format.extension());
+    write_or_exit(&out_path, |mut file| {
+        output::write(program, options.format, &mut file)?;
+        file.flush()
+    });
+
+    if options.listing {
+        let listing = listing::listing(program);
+        write_or_exit(&side_path(options, in_path, "lst"), |file| write_to_file(file, listing.as_bytes()));
+    }
+    if options.symbols {
+        let symbol_map = listing::symbol_map(program);
+        write_or_exit(&side_path(options, in_path, "sym"), |file| write_to_file(file, symbol_map.as_bytes()));
+    }
+
+    print_status(&out_path, &format!("\n{} {:?} to {:?}\n\n", verb, source_name(in_path), out_path));
+    if options.summary {
+        print_status(&out_path, &listing::memory_map(program));
+    }
 }
 
 
-/// Write line to file
-/// 
+/// Assemble each .asm file to an object file next to it, printing
+/// diagnostics and exiting if any file has errors
+///
 /// # Arguments
-/// 
-/// * `file` - writable file
-/// * `line` - line to write to file
-fn write_to_file(mut file: &File, line: String) {
-    // Write the `LOREM_IPSUM` string to `file`, returns `io::Result<()>`
-    file.write_all(format!("{}\n", line).as_bytes()).expect("Failed to write line to file!");
+///
+/// * `options` - command line options
+fn assemble_objects(options: &Options) {
+    let assemble_options = AssembleOptions { optimize: options.optimize };
+    let mut failed = false;
+    for in_path in &options.in_paths {
+        let source = Source { name: source_name(in_path), contents: read_or_exit(in_path, "asm") };
+        let out_path = out_path(options, in_path, OBJECT_EXTENSION);
+        let name = out_path.to_string_lossy();
+        match assemble_object(&name, &[source], &|path| fs::read_to_string(path), &assemble_options) {
+            Ok(object) => {
+                for warning in &object.warnings {
+                    eprintln!("{}", diagnostic::render_warning(warning));
+                }
+                if !options.check {
+                    let text = object.to_text().join("\n") + "\n";
+                    write_or_exit(&out_path, |file| write_to_file(file, text.as_bytes()));
+                    print_status(&out_path, &format!("Assembled {:?} to {:?}\n", source_name(in_path), out_path));
+                }
+            },
+            Err(errors) => {
+                for error in &errors {
+                    eprintln!("{}", diagnostic::render(error));
+                }
+                let plural = if errors.len() == 1 { "" } else { "s" };
+                eprintln!("error: could not assemble {:?} due to {} previous error{}",
+                    source_name(in_path), errors.len(), plural);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
 }
 
 
-/// Parse command line arguments and return input file
-/// contents and output file to write to
-fn parse_args() -> (String, File, String, String) {
-    // get user args
-    let args: Vec<String> = env::args().collect();
-
-    // check user args
-    if args.len() < 2 {
-        println!("Missing required argument!");
-        println!("Usage: cargo run FILENAME");
-        panic!();
+/// Link object files to an output file next to the first one, printing
+/// errors and exiting if they can't be linked
+///
+/// # Arguments
+///
+/// * `options` - command line options
+fn link_files(options: &Options) {
+    let in_path = options.in_paths[0].as_str();
+    let objects: Result<Vec<Object>, _> = options.in_paths.iter()
+        .map(|path| Object::from_text(path, &read_or_exit(path, OBJECT_EXTENSION)))
+        .collect();
+    let program = match objects.map_err(|error| vec![error]).and_then(|objects| link(&objects)) {
+        Ok(program) => program,
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("error: {}", error);
+            }
+            let plural = if errors.len() == 1 { "" } else { "s" };
+            eprintln!("error: could not link {:?} due to {} previous error{}",
+                in_path, errors.len(), plural);
+            process::exit(1);
+        }
     };
 
-    let in_path_str = &args[1];
-    let in_path = Path::new(in_path_str);
-    let out_path_str = args[1].replace(".asm", ".hack");
-    let out_path = Path::new(&out_path_str);
-
-    let file_contents = get_file_contents(in_path, "asm");
-    let output_file = create_file(out_path);
-
-    (file_contents, output_file, in_path_str.to_string(), out_path_str.to_string())
-}
-
-#[test]
-fn test_parse_args() {
-    // not implemented
+    if !options.check {
+        write_program(&program, in_path, "Linked", options);
+    }
 }
 
 
-fn main() {
-
-    // define C command hashmaps
-    let mut dest_map: HashMap<&str, String> = HashMap::new();
-    dest_map.insert("",    "000".to_string());
-    dest_map.insert("M",   "001".to_string());
-    dest_map.insert("D",   "010".to_string());
-    dest_map.insert("MD",  "011".to_string());
-    dest_map.insert("A",   "100".to_string());
-    dest_map.insert("AM",  "101".to_string());
-    dest_map.insert("AD",  "110".to_string());
-    dest_map.insert("AMD", "111".to_string());
-
-    let mut comp_map: HashMap<&str, String> = HashMap::new();
-    comp_map.insert("0",   "0101010".to_string());
-    comp_map.insert("1",   "0111111".to_string());
-    comp_map.insert("-1",  "0111010".to_string());
-    comp_map.insert("D",   "0001100".to_string());
-    comp_map.insert("A",   "0110000".to_string());
-    comp_map.insert("M",   "1110000".to_string());
-    comp_map.insert("!D",  "0001101".to_string());
-    comp_map.insert("!A",  "0110001".to_string());
-    comp_map.insert("!M",  "1110001".to_string());
-    comp_map.insert("-D",  "0001111".to_string());
-    comp_map.insert("-A",  "0110011".to_string());
-    comp_map.insert("-M",  "1110011".to_string());
-    comp_map.insert("D+1", "0011111".to_string());
-    comp_map.insert("A+1", "0110111".to_string());
-    comp_map.insert("M+1", "1110111".to_string());
-    comp_map.insert("D-1", "0001110".to_string());
-    comp_map.insert("A-1", "0110010".to_string());
-    comp_map.insert("M-1", "1110010".to_string());
-    comp_map.insert("D+A", "0000010".to_string());
-    comp_map.insert("D+M", "1000010".to_string());
-    comp_map.insert("D-A", "0010011".to_string());
-    comp_map.insert("D-M", "1010011".to_string());
-    comp_map.insert("A-D", "0000111".to_string());
-    comp_map.insert("M-D", "1000111".to_string());
-    comp_map.insert("D&A", "0000000".to_string());
-    comp_map.insert("D&M", "1000000".to_string());
-    comp_map.insert("D|A", "0010101".to_string());
-    comp_map.insert("D|M", "1010101".to_string());
-
-    let mut jump_map: HashMap<&str, String> = HashMap::new();
-    jump_map.insert("",    "000".to_string());
-    jump_map.insert("JGT", "001".to_string());
-    jump_map.insert("JEQ", "010".to_string());
-    jump_map.insert("JGE", "011".to_string());
-    jump_map.insert("JLT", "100".to_string());
-    jump_map.insert("JNE", "101".to_string());
-    jump_map.insert("JLE", "110".to_string());
-    jump_map.insert("JMP", "111".to_string());
-
-    // define initial symbol map of builtins TODO: &str instead of String
-    let mut symbol_map: HashMap<&str, String> = HashMap::new();
-    symbol_map.insert("SP", "0".to_string());
-    symbol_map.insert("LCL", "1".to_string());
-    symbol_map.insert("ARG", "2".to_string());
-    symbol_map.insert("THIS", "3".to_string());
-    symbol_map.insert("THAT", "4".to_string());
-    symbol_map.insert("R0", "0".to_string());
-    symbol_map.insert("R1", "1".to_string());
-    symbol_map.insert("R2", "2".to_string());
-    symbol_map.insert("R3", "3".to_string());
-    symbol_map.insert("R4", "4".to_string());
-    symbol_map.insert("R5", "5".to_string());
-    symbol_map.insert("R6", "6".to_string());
-    symbol_map.insert("R7", "7".to_string());
-    symbol_map.insert("R8", "8".to_string());
-    symbol_map.insert("R9", "9".to_string());
-    symbol_map.insert("R10", "10".to_string());
-    symbol_map.insert("R11", "11".to_string());
-    symbol_map.insert("R12", "12".to_string());
-    symbol_map.insert("R13", "13".to_string());
-    symbol_map.insert("R14", "14".to_string());
-    symbol_map.insert("R15", "15".to_string());
-    symbol_map.insert("SCREEN", "16384".to_string());
-    symbol_map.insert("KBD", "24576".to_string());
-
-    let (file_contents, output_file, in_path, out_path) = parse_args();
-
-    // first pass: add L symbols to symbol table
-    let mut line_count = -1;
-    for line in file_contents.lines() {
-
-        // strip comments
-        let stripped_line = remove_comments(&line);
-        if stripped_line == "" { continue };
-
-        line_count += 1;
-        
-        // get command type of line
-        let command_type = get_command_type(stripped_line);
-
-        if let CommandType::L = command_type { // this is a condensed match
-            let symbol = get_symbol_l_command(stripped_line);
-
-            match symbol_map.get(&symbol) {
-                Some(_) => { continue },
-                _ => { // if symbol not already in table
-                    symbol_map.insert(symbol, line_count.to_string());
-                    line_count -= 1; // don't count label symbol as a line
-                }
-            }
+/// Disassemble .hack file and print the assembly, or write it to the
+/// --output path
+///
+/// # Arguments
+///
+/// * `options` - command line options
+fn disassemble_file(options: &Options) {
+    let in_path = options.in_paths[0].as_str();
+    let file_contents = read_or_exit(in_path, "hack");
+
+    match disassemble(&file_contents) {
+        Ok(_) if options.check => {},
+        Ok(asm) => {
+            let out_path = options.out_path.as_deref().unwrap_or(STDIO);
+            write_or_exit(Path::new(out_path), |file| write_to_file(file, asm.as_bytes()));
+        },
+        Err(error) => {
+            eprintln!("error: {}:{}", in_path, error);
+            process::exit(1);
         }
     }
+}
 
-    // second pass: replace symbols with numbers, convert to bits
-    let mut var_count = 16;
-    for line in file_contents.lines() {
-
-        let stripped_line = remove_comments(&line);
-        if stripped_line == "" { continue };
-        
-        let command_type = get_command_type(stripped_line);
-
-        let parsed_line = match command_type {
-            CommandType::A => {
-                if a_command_contains_symbol(stripped_line) {
-                    let symbol = get_symbol_a_command(stripped_line);
-
-                    // put var number in symbol table if not already
-                    match symbol_map.get(&symbol) {
-                        Some(_) => {}, // do nothing if already in table
-                        _ => { // if symbol not already in table
-                            symbol_map.insert(&symbol, var_count.to_string());
-                            var_count += 1; // increment variables declared
-                        }
-                    }
-                    
-                    // replace symbol with number in line if necessary
-                    match symbol {
-                        "" => stripped_line.to_string(),
-                        _ => stripped_line.replace(symbol, symbol_map.get(&symbol).unwrap())
-                    }
-                }
-                else {
-                    stripped_line.to_string()
-                }
-            }
-            CommandType::L => {
-                continue // no bits for L commands
-            }
-            CommandType::C => {
-                stripped_line.to_string()
-            }
-        };
 
-        // create bit line based on parsed
-        let bits = match command_type {
-            CommandType::A => {
-                get_a_bits(&parsed_line)
-            }
-            CommandType::C => {
-                get_c_bits(&parsed_line, &comp_map, &dest_map, &jump_map)
-            }
-            CommandType::L => {
-                panic!();
-            }
-        };
+fn main() {
 
-        write_to_file(&output_file, bits);
-    }
+    let options = parse_args();
 
-    println!("\nAssembled {:?} to {:?}\n", in_path, out_path);
+    match Path::new(&options.in_paths[0]).extension().and_then(|ext| ext.to_str()) {
+        Some("hack") => disassemble_file(&options),
+        Some(OBJECT_EXTENSION) => link_files(&options),
+        _ if options.object => assemble_objects(&options),
+        _ => assemble_files(&options)
+    }
 }
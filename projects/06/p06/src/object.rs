@@ -0,0 +1,117 @@
+// Relocatable object files of assembled Hack code, to be combined by the linker
+// Author: Leo Robinovitch
+
+use crate::link::{LinkError, LinkErrorKind};
+use crate::lint::Warning;
+
+/// File extension of object files
+pub const OBJECT_EXTENSION: &str = "hobj";
+
+/// First line of an object file
+const OBJECT_HEADER: &str = "HOBJ 1";
+
+
+/// Assembled code whose labels are relative to the start of the object.
+/// The linker places objects one after another in ROM, adds the base
+/// address of the object at each relocation and fills in each reference.
+#[derive(PartialEq, Eq, Debug, Default, Clone)]
+pub struct Object {
+    /// name of the object, used in link errors
+    pub name: String,
+    /// one 16 bit word per instruction, 0 at references
+    pub words: Vec<u16>,
+    /// labels and their addresses relative to the object, in source order.
+    /// Labels starting with LOCAL_PREFIX are qualified with their file.
+    pub labels: Vec<(String, u16)>,
+    /// addresses of A commands holding an address relative to the object
+    pub relocations: Vec<u16>,
+    /// addresses of A commands and the symbol they load, a label of
+    /// another object or a variable
+    pub references: Vec<(u16, String)>,
+    /// likely mistakes found by the lint pass, not kept in object files
+    pub warnings: Vec<Warning>,
+}
+
+impl Object {
+    /// Returns the lines of an object file: a header, then a line per
+    /// word, label, relocation and reference
+    pub fn to_text(&self) -> Vec<String> {
+        let mut lines = vec![OBJECT_HEADER.to_string()];
+        lines.extend(self.words.iter().map(|word| format!("W {:016b}", word)));
+        lines.extend(self.labels.iter().map(|(label, address)| format!("L {} {}", address, label)));
+        lines.extend(self.relocations.iter().map(|address| format!("R {}", address)));
+        lines.extend(self.references.iter().map(|(address, symbol)| format!("F {} {}", address, symbol)));
+        lines
+    }
+
+    /// Reads an object from the contents of an object file
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - name of the object, used in link errors
+    /// * `text` - contents of an object file
+    pub fn from_text(name: &str, text: &str) -> Result<Object, LinkError> {
+        let invalid = |line: &str| LinkError::new(LinkErrorKind::InvalidObject, name, line);
+        let mut object = Object { name: name.to_string(), ..Default::default() };
+
+        let mut lines = text.lines();
+        match lines.next() {
+            Some(OBJECT_HEADER) => {},
+            line => return Err(invalid(line.unwrap_or("")))
+        }
+
+        for line in lines {
+            let mut fields = line.splitn(3, ' ');
+            let record = (fields.next(), fields.next(), fields.next());
+            let address = |field: &str| field.parse::<u16>().ok()
+                .filter(|address| (*address as usize) < object.words.len());
+            match record {
+                (Some("W"), Some(bits), None) if bits.len() == 16 => {
+                    object.words.push(u16::from_str_radix(bits, 2).map_err(|_| invalid(line))?);
+                },
+                (Some("L"), Some(field), Some(label)) => {
+                    // labels may point just past the last word
+                    let address = field.parse::<u16>().ok()
+                        .filter(|address| (*address as usize) <= object.words.len());
+                    object.labels.push((label.to_string(), address.ok_or_else(|| invalid(line))?));
+                },
+                (Some("R"), Some(field), None) => {
+                    object.relocations.push(address(field).ok_or_else(|| invalid(line))?);
+                },
+                (Some("F"), Some(field), Some(symbol)) => {
+                    object.references.push((address(field).ok_or_else(|| invalid(line))?, symbol.to_string()));
+                },
+                _ => return Err(invalid(line))
+            }
+        }
+        Ok(object)
+    }
+}
+
+#[test]
+fn test_object_text() {
+    let object = Object {
+        name: "a.hobj".to_string(),
+        words: vec![2, 0, 0b1110101010000111],
+        labels: vec![("LOOP".to_string(), 0), ("_end@a.asm".to_string(), 3)],
+        relocations: vec![0],
+        references: vec![(1, "x".to_string())],
+        warnings: Vec::new(),
+    };
+    let text = object.to_text().join("\n");
+    assert_eq!("HOBJ 1\n\
+        W 0000000000000010\n\
+        W 0000000000000000\n\
+        W 1110101010000111\n\
+        L 0 LOOP\n\
+        L 3 _end@a.asm\n\
+        R 0\n\
+        F 1 x", text);
+    assert_eq!(Ok(object), Object::from_text("a.hobj", &text));
+
+    let error = Object::from_text("a.hobj", "HOBJ 1\nW 0\n").unwrap_err();
+    assert_eq!((LinkErrorKind::InvalidObject, "W 0"), (error.kind, error.text.as_str()));
+    let error = Object::from_text("a.hobj", "HOBJ 1\nW 0000000000000000\nR 1\n").unwrap_err();
+    assert_eq!("R 1", error.text);
+    assert!(Object::from_text("a.hobj", "0000000000000000\n").is_err());
+}
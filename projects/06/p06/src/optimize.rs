@@ -0,0 +1,550 @@
+// Peephole optimizer for parsed Hack instructions, run before labels
+// are given addresses
+// Author: Leo Robinovitch
+
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use crate::data::Directive;
+use crate::local::Scope;
+use crate::parser::{Instruction, Operand};
+use crate::ParsedLine as Line;
+use crate::{expr, get_equ, qualify, AssembleError, ErrorKind, LineError, SourceLine, MAX_A_VALUE};
+
+
+/// Returns errors for the ROM addresses the optimizer would break by
+/// removing instructions: labels used in expressions, equ constants and
+/// data words, which would keep their old value, and numbers other than
+/// 0, the start of the program, loaded into A for a jump
+///
+/// # Arguments
+///
+/// * `instructions` - parsed instructions with their source and stripped lines
+/// * `equs` - equ directives with their source lines
+/// * `data` - data directives with their source and stripped lines
+/// * `errors` - errors found are pushed here
+pub(crate) fn check_addresses(
+    instructions: &[Line],
+    equs: &[(&SourceLine, &str)],
+    data: &[(&SourceLine, &str, Directive)],
+    errors: &mut Vec<AssembleError>) {
+
+    let mut scope = Scope::default();
+    let labels: HashSet<String> = instructions.iter()
+        .filter_map(|(line, _, instruction)| match instruction {
+            Instruction::Label(label) => scope.declare(line, label).ok().map(|name| qualify(name, &line.file).into_owned()),
+            _ => None
+        })
+        .collect();
+    let error = |line: &SourceLine, stripped_line: &str, text: &str| {
+        LineError::new(ErrorKind::FixedAddress, stripped_line, text).locate(line, stripped_line)
+    };
+
+    let mut scope = Scope::default();
+    let mut jump_target: Option<(&SourceLine, &str)> = None;
+    for (line, stripped_line, instruction) in instructions {
+        match instruction {
+            Instruction::A(Operand::Expression(expr)) => {
+                for symbol in expr::symbols(expr) {
+                    if scope.resolve(line, symbol).is_ok_and(|name| labels.contains(qualify(name, &line.file).as_ref())) {
+                        errors.push(error(line, stripped_line, symbol));
+                    }
+                }
+                jump_target = None;
+            },
+            Instruction::A(Operand::Number(value)) if *value != 0 => jump_target = Some((line, stripped_line)),
+            Instruction::A(_) => jump_target = None,
+            Instruction::C { dest, jump, .. } => {
+                if let Some((line, stripped_line)) = jump_target.filter(|_| !jump.is_empty()) {
+                    errors.push(error(line, stripped_line, &stripped_line[1..]));
+                    jump_target = None;
+                }
+                if dest.contains('A') { jump_target = None };
+            },
+            Instruction::Label(label) => {
+                let _ = scope.declare(line, label); // errors are found defining labels
+                jump_target = None;
+            }
+        }
+    }
+
+    for (line, stripped_line) in equs {
+        if let Ok((_, expr)) = get_equ(stripped_line) {
+            for symbol in expr::symbols(expr) {
+                if labels.contains(qualify(symbol, &line.file).as_ref()) {
+                    errors.push(error(line, stripped_line, symbol));
+                }
+            }
+        }
+    }
+    for (line, stripped_line, directive) in data {
+        let exprs = match directive {
+            Directive::Data(expr) => std::slice::from_ref(expr),
+            Directive::Word(exprs) => exprs.as_slice(),
+            Directive::String(_) => &[],
+        };
+        for symbol in exprs.iter().flat_map(|expr| expr::symbols(expr)) {
+            if labels.contains(qualify(symbol, &line.file).as_ref()) {
+                errors.push(error(line, stripped_line, symbol));
+            }
+        }
+    }
+}
+
+
+/// Checks if an instruction is a C command with the given dest and comp
+/// and no jump
+///
+/// # Arguments
+///
+/// * `instruction` - the instruction
+/// * `dest` - the dest mnemonic to match
+/// * `comp` - the comp mnemonic to match
+fn is_assignment(instruction: &Instruction, dest: &str, comp: &str) -> bool {
+    match instruction {
+        Instruction::C { dest: d, comp: c, jump: "" } => *d == dest && *c == comp,
+        _ => false
+    }
+}
+
+
+/// Drops the instructions after an unconditional jump up to the next
+/// label, since nothing can reach them. Returns true if any were dropped.
+///
+/// # Arguments
+///
+/// * `instructions` - instructions to optimize
+fn drop_unreachable(instructions: &mut Vec<Line>) -> bool {
+    let before = instructions.len();
+    let mut reachable = true;
+    instructions.retain(|(_, _, instruction)| {
+        match instruction {
+            Instruction::Label(_) => reachable = true,
+            _ if !reachable => return false,
+            Instruction::C { jump: "JMP", .. } => reachable = false,
+            _ => {}
+        }
+        true
+    });
+    instructions.len() != before
+}
+
+
+/// Drops A commands that load the value A already holds, e.g. the second
+/// `@SP` in `@SP / AM=M-1 / ... / @SP` when nothing in between writes A.
+/// Returns true if any were dropped.
+///
+/// # Arguments
+///
+/// * `instructions` - instructions to optimize
+fn drop_repeated_loads(instructions: &mut Vec<Line>) -> bool {
+    let before = instructions.len();
+    let mut loaded: Option<(Operand, Rc<str>)> = None;
+    instructions.retain(|(line, _, instruction)| {
+        match instruction {
+            Instruction::A(operand) => {
+                let load = Some((operand.clone(), line.file.clone()));
+                if loaded == load { return false };
+                loaded = load;
+            },
+            Instruction::C { dest, .. } if dest.contains('A') => loaded = None,
+            Instruction::C { .. } => {},
+            Instruction::Label(_) => loaded = None // may be reached with any A
+        }
+        true
+    });
+    instructions.len() != before
+}
+
+
+/// Returns the comp that computes `comp` without A, when A holds `value`
+///
+/// # Arguments
+///
+/// * `comp` - comp mnemonic that reads A
+/// * `value` - the value of A
+fn fold_comp(comp: &str, value: u16) -> Option<&'static str> {
+    match (comp, value) {
+        ("A", 0) | ("-A", 0) => Some("0"),
+        ("A", 1) => Some("1"),
+        ("-A", 1) | ("!A", 0) => Some("-1"),
+        ("D+A", 0) | ("D-A", 0) | ("D|A", 0) => Some("D"),
+        ("D+A", 1) => Some("D+1"),
+        ("D-A", 1) => Some("D-1"),
+        ("A-D", 0) => Some("-D"),
+        ("D&A", 0) => Some("0"),
+        _ => None
+    }
+}
+
+#[test]
+fn test_fold_comp() {
+    assert_eq!(Some("1"), fold_comp("A", 1));
+    assert_eq!(Some("D-1"), fold_comp("D-A", 1));
+    assert_eq!(None, fold_comp("A", 2));
+    assert_eq!(None, fold_comp("M", 0));
+}
+
+
+/// Returns the value of D after `D=<comp>`, or after `@<value> / D=<comp>`
+/// if the comp reads A, when it is a constant
+///
+/// # Arguments
+///
+/// * `comp` - comp mnemonic
+/// * `value` - the value of A, if known
+fn constant(comp: &str, value: Option<u16>) -> Option<i32> {
+    match (comp, value) {
+        ("0", _) => Some(0),
+        ("1", _) => Some(1),
+        ("-1", _) => Some(-1),
+        ("A", Some(value)) => Some(value as i32),
+        _ => None
+    }
+}
+
+
+/// Returns the A command value and comp that leave the same D as
+/// `@x / D=<first> / @y / D=<second>`, where `first` is A, D+A or D-A
+/// and `second` is D+A or D-A
+///
+/// # Arguments
+///
+/// * `x` - the first value loaded
+/// * `first` - the first comp
+/// * `y` - the second value loaded
+/// * `second` - the second comp
+fn fold_sum(x: u16, first: &str, y: u16, second: &str) -> Option<(u16, &'static str)> {
+    let y = match second {
+        "D+A" => y as i32,
+        "D-A" => -(y as i32),
+        _ => return None
+    };
+    let (sum, comp) = match first {
+        "A" => (x as i32 + y, "A"),
+        "D+A" => (x as i32 + y, "D+A"),
+        "D-A" => (y - x as i32, "D+A"),
+        _ => return None
+    };
+    let (sum, comp) = if comp == "D+A" && sum < 0 { (-sum, "D-A") } else { (sum, comp) };
+    if (0..=MAX_A_VALUE).contains(&sum) { Some((sum as u16, comp)) } else { None }
+}
+
+#[test]
+fn test_fold_sum() {
+    assert_eq!(Some((3, "A")), fold_sum(0, "A", 3, "D+A"));
+    assert_eq!(Some((6, "D-A")), fold_sum(1, "D-A", 5, "D-A"));
+    assert_eq!(Some((2, "D+A")), fold_sum(3, "D-A", 5, "D+A"));
+    assert_eq!(None, fold_sum(1, "A", 5, "D-A"));
+    assert_eq!(None, fold_sum(1, "D+A", 5, "D&A"));
+}
+
+
+/// Folds `@x / D=...A...` into `D=...` when x is 0 or 1, and constants in
+/// `@x / D=A / @y / D=D+A` or `@x / D=D-A / @y / D=D-A` into one A command,
+/// when the next instruction loads A anyway, e.g. `@1 / D=A / @SP` becomes
+/// `D=1 / @SP`. Returns true if any were folded.
+///
+/// # Arguments
+///
+/// * `instructions` - instructions to optimize
+fn fold_constants(instructions: &mut Vec<Line>) -> bool {
+    let mut changed = false;
+    let mut idx = 0;
+    while idx + 2 < instructions.len() {
+        let window: Vec<&Instruction> = instructions[idx..(idx + 5).min(instructions.len())].iter()
+            .map(|(_, _, instruction)| instruction)
+            .collect();
+        let sum = match window[..] {
+            [Instruction::A(Operand::Number(x)), Instruction::C { dest: "D", comp: first, jump: "" },
+             Instruction::A(Operand::Number(y)), Instruction::C { dest: "D", comp: second, jump: "" },
+             Instruction::A(_)] => fold_sum(*x, first, *y, second),
+            _ => None
+        };
+        let folded = match window[..] {
+            [Instruction::A(Operand::Number(value)), Instruction::C { dest: "D", comp, jump: "" }, Instruction::A(_), ..] => {
+                fold_comp(comp, *value)
+            },
+            _ => None
+        };
+
+        if let Some((value, comp)) = sum {
+            instructions[idx].2 = Instruction::A(Operand::Number(value));
+            instructions[idx + 1].2 = Instruction::C { dest: "D", comp, jump: "" };
+            instructions.drain(idx + 2..idx + 4);
+            changed = true;
+            continue; // may fold with the next pair too
+        }
+        if let Some(comp) = folded {
+            instructions[idx + 1].2 = Instruction::C { dest: "D", comp, jump: "" };
+            instructions.remove(idx);
+            changed = true;
+        }
+        idx += 1;
+    }
+    changed
+}
+
+
+/// Returns the comp that computes `comp` without D, when D holds `value`
+///
+/// # Arguments
+///
+/// * `comp` - comp mnemonic that reads D
+/// * `value` - the value of D
+fn fold_d_comp(comp: &str, value: i32) -> Option<&'static str> {
+    match (comp, value) {
+        ("D", 0) | ("-D", 0) | ("D&A", 0) | ("D&M", 0) | ("D-1", 1) => Some("0"),
+        ("D", 1) | ("D+1", 0) => Some("1"),
+        ("D", -1) | ("-D", 1) | ("!D", 0) | ("D-1", 0) => Some("-1"),
+        ("D+A", 0) | ("D|A", 0) | ("A-D", 0) => Some("A"),
+        ("D+M", 0) | ("D|M", 0) | ("M-D", 0) => Some("M"),
+        ("D-A", 0) => Some("-A"),
+        ("D-M", 0) => Some("-M"),
+        ("D+A", 1) => Some("A+1"),
+        ("D+M", 1) => Some("M+1"),
+        ("A-D", 1) => Some("A-1"),
+        ("M-D", 1) => Some("M-1"),
+        _ => None
+    }
+}
+
+#[test]
+fn test_fold_d_comp() {
+    assert_eq!(Some("A"), fold_d_comp("D+A", 0));
+    assert_eq!(Some("M-1"), fold_d_comp("M-D", 1));
+    assert_eq!(None, fold_d_comp("D+A", 2));
+    assert_eq!(None, fold_d_comp("M", 0));
+}
+
+
+/// Rewrites comps that read D to comps without D where D holds 0, 1 or -1
+/// since `D=0`, `D=1` or `D=-1` in the same block, e.g. `D=0 / @ARG / A=M /
+/// A=D+A` becomes `D=0 / @ARG / A=M / A=A`. Returns true if any were rewritten.
+///
+/// # Arguments
+///
+/// * `instructions` - instructions to optimize
+fn fold_known_d(instructions: &mut [Line]) -> bool {
+    let mut changed = false;
+    let mut known: Option<i32> = None;
+    for (_, _, instruction) in instructions.iter_mut() {
+        match instruction {
+            Instruction::C { dest, comp, .. } => {
+                if let Some(folded) = known.and_then(|value| fold_d_comp(comp, value)) {
+                    *comp = folded;
+                    changed = true;
+                }
+                if dest.contains('D') { known = constant(comp, None) };
+            },
+            Instruction::Label(_) => known = None, // may be reached with any D
+            Instruction::A(_) => {}
+        }
+    }
+    changed
+}
+
+
+/// Drops C commands without a jump that change nothing, like `A=A` or a
+/// comp without a dest, and `D=...` or A commands loading a number whose
+/// value is replaced before anything reads it. Returns true if any were dropped.
+///
+/// # Arguments
+///
+/// * `instructions` - instructions to optimize
+fn drop_dead_writes(instructions: &mut Vec<Line>) -> bool {
+    let mut dead: Vec<bool> = vec![false; instructions.len()];
+    for (idx, (_, _, instruction)) in instructions.iter().enumerate() {
+        dead[idx] = match instruction {
+            Instruction::C { dest, comp, jump: "" } if dest.is_empty() || dest == comp => true,
+            Instruction::C { dest: "D", jump: "", .. } => {
+                // D is dead if written again before it is read or control moves
+                instructions[idx + 1..].iter()
+                    .map(|(_, _, next)| match next {
+                        Instruction::C { comp, jump, .. } if comp.contains('D') || !jump.is_empty() => Some(false),
+                        Instruction::C { dest, .. } if dest.contains('D') => Some(true),
+                        Instruction::Label(_) => Some(false),
+                        _ => None
+                    })
+                    .find_map(|dead| dead)
+                    .unwrap_or(false)
+            },
+            // variables are allocated in order of first use, so only numbers
+            Instruction::A(Operand::Number(_)) => {
+                matches!(instructions.get(idx + 1), Some((_, _, Instruction::A(_))))
+            },
+            _ => false
+        };
+    }
+    let before = instructions.len();
+    let mut idx = 0;
+    instructions.retain(|_| {
+        idx += 1;
+        !dead[idx - 1]
+    });
+    instructions.len() != before
+}
+
+
+/// Drops `D=M` or `M=D` that copy between D and the same memory as an
+/// earlier `M=D` or `D=M`, when neither D nor memory was written since,
+/// e.g. the second of `@x / M=D / @x / D=M`. Returns true if any were dropped.
+///
+/// # Arguments
+///
+/// * `instructions` - instructions to optimize
+fn drop_round_trips(instructions: &mut Vec<Line>) -> bool {
+    let before = instructions.len();
+    // the operand A was loaded with, and the one D holds the memory of
+    let mut loaded: Option<(Operand, Rc<str>)> = None;
+    let mut copied: Option<(Operand, Rc<str>)> = None;
+    instructions.retain(|(line, _, instruction)| {
+        let copy = is_assignment(instruction, "D", "M") || is_assignment(instruction, "M", "D");
+        match instruction {
+            Instruction::A(operand) => loaded = Some((operand.clone(), line.file.clone())),
+            Instruction::C { .. } if copy && loaded.is_some() && loaded == copied => return false,
+            Instruction::C { .. } if copy => copied = loaded.clone(),
+            Instruction::C { dest, .. } => {
+                // other memory may share an address with what D holds
+                if dest.contains('D') || dest.contains('M') { copied = None };
+                if dest.contains('A') { loaded = None };
+            },
+            Instruction::Label(_) => {
+                loaded = None;
+                copied = None;
+            }
+        }
+        true
+    });
+    instructions.len() != before
+}
+
+
+/// Merges `M=M+1 / AM=M-1`, e.g. a VM push directly followed by a pop,
+/// into `A=M`, which leaves M as it was and points A at the popped value.
+/// Returns true if any were merged.
+///
+/// # Arguments
+///
+/// * `instructions` - instructions to optimize
+fn merge_push_pop(instructions: &mut Vec<Line>) -> bool {
+    let mut changed = false;
+    let mut idx = 1;
+    while idx < instructions.len() {
+        if is_assignment(&instructions[idx - 1].2, "M", "M+1") && is_assignment(&instructions[idx].2, "AM", "M-1") {
+            instructions[idx].2 = Instruction::C { dest: "A", comp: "M", jump: "" };
+            instructions.remove(idx - 1);
+            changed = true;
+        } else {
+            idx += 1;
+        }
+    }
+    changed
+}
+
+
+/// Rewrites `@SP / A=M / M=D / @SP / M=M+1`, a VM push, into `@SP /
+/// AM=M+1 / A=A-1 / M=D` when the next instruction loads A anyway, since
+/// only A is left different. Returns true if any were rewritten.
+///
+/// # Arguments
+///
+/// * `instructions` - instructions to optimize
+fn shorten_pushes(instructions: &mut Vec<Line>) -> bool {
+    let mut changed = false;
+    let mut idx = 0;
+    while idx + 5 < instructions.len() {
+        let push = match (&instructions[idx].2, &instructions[idx + 3].2, &instructions[idx + 5].2) {
+            (Instruction::A(pointer), Instruction::A(again), Instruction::A(_)) => {
+                pointer == again && instructions[idx].0.file == instructions[idx + 3].0.file
+                    && is_assignment(&instructions[idx + 1].2, "A", "M")
+                    && is_assignment(&instructions[idx + 2].2, "M", "D")
+                    && is_assignment(&instructions[idx + 4].2, "M", "M+1")
+            },
+            _ => false
+        };
+        if push {
+            instructions[idx + 1].2 = Instruction::C { dest: "AM", comp: "M+1", jump: "" };
+            instructions[idx + 2].2 = Instruction::C { dest: "A", comp: "A-1", jump: "" };
+            instructions[idx + 3].2 = Instruction::C { dest: "M", comp: "D", jump: "" };
+            instructions.remove(idx + 4);
+            changed = true;
+        }
+        idx += 1;
+    }
+    changed
+}
+
+
+/// Returns the instructions rewritten to fewer instructions that leave
+/// the registers and memory the same wherever the program can observe them
+///
+/// # Arguments
+///
+/// * `instructions` - parsed instructions with their source and stripped lines
+pub(crate) fn optimize(mut instructions: Vec<Line>) -> Vec<Line> {
+    loop {
+        let mut changed = drop_unreachable(&mut instructions);
+        changed |= drop_repeated_loads(&mut instructions);
+        changed |= fold_constants(&mut instructions);
+        changed |= fold_known_d(&mut instructions);
+        changed |= drop_dead_writes(&mut instructions);
+        changed |= drop_round_trips(&mut instructions);
+        changed |= merge_push_pop(&mut instructions);
+        changed |= shorten_pushes(&mut instructions);
+        if !changed { break };
+    }
+    instructions
+}
+
+#[test]
+fn test_optimize() {
+    let optimized = |source: &str| {
+        let options = crate::Options { optimize: true };
+        let program = crate::assemble_sources(
+            &[crate::Source { name: String::new(), contents: source.to_string() }],
+            &|_| Err(std::io::Error::new(std::io::ErrorKind::NotFound, "not found")),
+            &options).unwrap();
+        crate::disassemble::disassemble(&program.to_hack().join("\n")).unwrap()
+    };
+
+    // push constant 1, then pop into D: the repeated @SP, the constant and
+    // the stack pointer update fold away
+    let source = "@1\nD=A\n@SP\nA=M\nM=D\n@SP\nM=M+1\n@SP\nAM=M-1\nD=M\n";
+    assert_eq!("@0\nA=M\nM=1\n@0\nA=M\nD=M\n", optimized(source));
+
+    // pushes only leave A different, unless a label may read it next
+    let source = "@LCL\nA=M\nD=M\n@SP\nA=M\nM=D\n@SP\nM=M+1\n@2\nD=A\n@SP\nA=M\nM=D\n@SP\nM=M+1\n(END)\n@END\n0;JMP\n";
+    assert_eq!("@1\nA=M\nD=M\n@0\nAM=M+1\nA=A-1\nM=D\n@2\nD=A\n@0\nA=M\nM=D\n@0\nM=M+1\n(L_0014)\n@L_0014\n0;JMP\n",
+        optimized(source));
+
+    // constants: pop pointer 0, ARG = SP - 1 - 5 and push argument 0
+    let source = "@0\nD=A\n@3\nD=D+A\n@R13\nM=D\n";
+    assert_eq!("@3\nD=A\n@13\nM=D\n", optimized(source));
+    let source = "@SP\nD=M\n@1\nD=D-A\n@5\nD=D-A\n@ARG\nM=D\n";
+    assert_eq!("@0\nD=M\n@6\nD=D-A\n@2\nM=D\n", optimized(source));
+    let source = "@0\nD=A\n@ARG\nA=M\nA=D+A\nD=M\n";
+    assert_eq!("@2\nA=M\nD=M\n", optimized(source));
+
+    // unreachable code after a jump, up to the next label
+    let source = "(LOOP)\n@LOOP\n0;JMP\n@5\nD=A\n(END)\n@END\n0;JMP\nD=M";
+    assert_eq!("(L_0000)\n@L_0000\n0;JMP\n(L_0002)\n@L_0002\n0;JMP\n", optimized(source));
+
+    // round trips, also with A loaded again and jumps in between
+    let source = "@x\nM=D\n@x\nD=M\n@y\nD=M\nM=D\n";
+    assert_eq!("@16\nM=D\n@17\nD=M\n", optimized(source));
+    let source = "@x\nD=M\n@SKIP\nD;JEQ\n@x\nM=D\n(SKIP)\n@y\nM=0\n";
+    assert_eq!("@16\nD=M\n@L_0005\nD;JEQ\n@16\n(L_0005)\n@17\nM=0\n", optimized(source));
+    // but not with memory written in between, which may be the same
+    let source = "@x\nD=M\n@y\nM=0\n@x\nM=D\n";
+    assert_eq!("@16\nD=M\n@17\nM=0\n@16\nM=D\n", optimized(source));
+
+    // removing instructions moves labels, so their addresses can't be used
+    let source = "(LOOP)\n@LOOP+1\n0;JMP\n@5\nD;JGT\n@0\n0;JMP\n.equ AFTER LOOP\n@SCREEN+1\n";
+    let errors = crate::assemble_sources(
+        &[crate::Source { name: String::new(), contents: source.to_string() }],
+        &|_| Err(std::io::Error::new(std::io::ErrorKind::NotFound, "not found")),
+        &crate::Options { optimize: true }).unwrap_err();
+    let found: Vec<(ErrorKind, usize, &str)> = errors.iter().map(|e| (e.kind, e.line, e.text.as_str())).collect();
+    assert_eq!(vec![(ErrorKind::FixedAddress, 2, "LOOP"), (ErrorKind::FixedAddress, 4, "5"), (ErrorKind::FixedAddress, 8, "LOOP")], found);
+}
@@ -0,0 +1,181 @@
+// Output formats for assembled Hack machine code
+// Author: Leo Robinovitch
+
+use std::io::{self, Write};
+
+use crate::Program;
+
+/// Format of the assembled output file
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum OutputFormat {
+    /// text lines of 16 binary digits, as read by the nand2tetris tools
+    Hack,
+    /// raw 16 bit words, most significant byte first
+    BinaryBigEndian,
+    /// raw 16 bit words, least significant byte first
+    BinaryLittleEndian,
+    /// Intel HEX records of big endian words, byte addressed
+    IntelHex,
+    /// Logisim `v2.0 raw` memory image
+    Logisim,
+}
+
+impl OutputFormat {
+    /// Returns the format for a command line name, e.g. `ihex`
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - name of the format
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name {
+            "hack" => Some(OutputFormat::Hack),
+            "bin-be" => Some(OutputFormat::BinaryBigEndian),
+            "bin-le" => Some(OutputFormat::BinaryLittleEndian),
+            "ihex" => Some(OutputFormat::IntelHex),
+            "logisim" => Some(OutputFormat::Logisim),
+            _ => None
+        }
+    }
+
+    /// Returns the file extension for the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Hack => "hack",
+            OutputFormat::BinaryBigEndian | OutputFormat::BinaryLittleEndian => "bin",
+            OutputFormat::IntelHex => "hex",
+            OutputFormat::Logisim => "rom",
+        }
+    }
+}
+
+#[test]
+fn test_output_format_from_name() {
+    assert_eq!(Some(OutputFormat::Hack), OutputFormat::from_name("hack"));
+    assert_eq!(Some(OutputFormat::BinaryLittleEndian), OutputFormat::from_name("bin-le"));
+    assert_eq!(None, OutputFormat::from_name("elf"));
+}
+
+
+/// Writes the program in the given format. Hack and binary words are
+/// written one at a time, so `out` should be buffered.
+///
+/// # Arguments
+///
+/// * `program` - assembled program
+/// * `format` - output format
+/// * `out` - where to write the encoded program
+pub fn write<W: Write>(program: &Program, format: OutputFormat, out: &mut W) -> io::Result<()> {
+    match format {
+        OutputFormat::Hack => {
+            for word in &program.words {
+                let mut line = [b'\n'; 17];
+                for (idx, digit) in line[..16].iter_mut().enumerate() {
+                    *digit = if word >> (15 - idx) & 1 == 1 { b'1' } else { b'0' };
+                }
+                out.write_all(&line)?;
+            }
+            Ok(())
+        },
+        OutputFormat::BinaryBigEndian => {
+            program.words.iter().try_for_each(|word| out.write_all(&word.to_be_bytes()))
+        },
+        OutputFormat::BinaryLittleEndian => {
+            program.words.iter().try_for_each(|word| out.write_all(&word.to_le_bytes()))
+        },
+        OutputFormat::IntelHex => out.write_all(encode_intel_hex(&program.words).as_bytes()),
+        OutputFormat::Logisim => out.write_all(encode_logisim(&program.words).as_bytes()),
+    }
+}
+
+
+/// Returns the program encoded in the given format
+///
+/// # Arguments
+///
+/// * `program` - assembled program
+/// * `format` - output format
+pub fn encode(program: &Program, format: OutputFormat) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(program.words.len() * 17);
+    write(program, format, &mut bytes).unwrap(); // writing to a Vec can't fail
+    bytes
+}
+
+#[test]
+fn test_encode() {
+    let program = Program { words: vec![0x0002, 0xec10], ..Default::default() };
+    assert_eq!(b"0000000000000010\n1110110000010000\n".to_vec(), encode(&program, OutputFormat::Hack));
+    assert_eq!(vec![0x00, 0x02, 0xec, 0x10], encode(&program, OutputFormat::BinaryBigEndian));
+    assert_eq!(vec![0x02, 0x00, 0x10, 0xec], encode(&program, OutputFormat::BinaryLittleEndian));
+}
+
+
+/// Returns Intel HEX data records of 8 words each, followed by the end
+/// of file record. A full 32K word ROM is 64K bytes, so no extended
+/// address records are needed.
+///
+/// # Arguments
+///
+/// * `words` - machine code words
+fn encode_intel_hex(words: &[u16]) -> String {
+    let mut hex = String::new();
+    for (idx, chunk) in words.chunks(8).enumerate() {
+        let address = (idx * 16) as u16;
+        let mut record: Vec<u8> = vec![(chunk.len() * 2) as u8];
+        record.extend_from_slice(&address.to_be_bytes());
+        record.push(0x00); // data record
+        for word in chunk {
+            record.extend_from_slice(&word.to_be_bytes());
+        }
+        hex.push_str(&format_record(&record));
+    }
+    hex.push_str(":00000001FF\n");
+    hex
+}
+
+#[test]
+fn test_encode_intel_hex() {
+    assert_eq!(":00000001FF\n", encode_intel_hex(&[]));
+    assert_eq!(":040000000002EC10FE\n:00000001FF\n", encode_intel_hex(&[0x0002, 0xec10]));
+    let words: Vec<u16> = (0..9).collect();
+    let hex = encode_intel_hex(&words);
+    assert_eq!(":020010000008E6", hex.lines().nth(1).unwrap());
+}
+
+
+/// Returns an Intel HEX record line with its checksum
+///
+/// # Arguments
+///
+/// * `record` - byte count, address, record type and data bytes
+fn format_record(record: &[u8]) -> String {
+    let sum = record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    let checksum = (!sum).wrapping_add(1);
+    let mut line = String::from(":");
+    for byte in record.iter().chain(std::iter::once(&checksum)) {
+        line.push_str(&format!("{:02X}", byte));
+    }
+    line.push('\n');
+    line
+}
+
+
+/// Returns a Logisim `v2.0 raw` memory image with 8 hex words per line
+///
+/// # Arguments
+///
+/// * `words` - machine code words
+fn encode_logisim(words: &[u16]) -> String {
+    let mut image = String::from("v2.0 raw\n");
+    for chunk in words.chunks(8) {
+        let line: Vec<String> = chunk.iter().map(|word| format!("{:x}", word)).collect();
+        image.push_str(&line.join(" "));
+        image.push('\n');
+    }
+    image
+}
+
+#[test]
+fn test_encode_logisim() {
+    assert_eq!("v2.0 raw\n", encode_logisim(&[]));
+    assert_eq!("v2.0 raw\n2 ec10\n", encode_logisim(&[0x0002, 0xec10]));
+}
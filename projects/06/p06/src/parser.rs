@@ -0,0 +1,401 @@
+// Lexer and parser for Hack instructions, run on each stripped line
+// Author: Leo Robinovitch
+//
+// instruction = '@' operand | '(' symbol ')' | [dest '='] comp [';' jump]
+// operand     = number | symbol | expression
+
+use std::collections::HashMap;
+
+use crate::{check_a_value, comp_map, dest_map, expr, jump_map, local, shift_comp_map, ErrorKind, LineError};
+
+lazy_static! { // lazy_static ensures the maps are only built once
+    static ref DESTS: HashMap<&'static str, u16> = dest_map();
+    static ref COMPS: HashMap<&'static str, u16> = comp_map().into_iter().chain(shift_comp_map()).collect();
+    static ref JUMPS: HashMap<&'static str, u16> = jump_map();
+}
+
+
+/// The value loaded by an A instruction. Symbols and expressions are
+/// slices of the parsed line.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Operand<'a> {
+    /// a literal, e.g. `@16` or `@0x4000`
+    Number(u16),
+    /// a label, variable, builtin or constant, e.g. `@LOOP`
+    Symbol(&'a str),
+    /// a constant expression, e.g. `@SCREEN+32*5`
+    Expression(&'a str),
+}
+
+
+/// A parsed line of Hack assembly. The dest, comp and jump of a C
+/// instruction are mnemonics found in `dest_map`, `comp_map` and `jump_map`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Instruction<'a> {
+    A(Operand<'a>),
+    C { dest: &'static str, comp: &'static str, jump: &'static str },
+    Label(&'a str),
+}
+
+
+/// Checks if a character can be part of a symbol
+///
+/// # Arguments
+///
+/// * `c` - the character
+pub fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$' || c == ':'
+}
+
+
+/// Checks if text is a valid symbol: symbol characters, not starting with a digit
+///
+/// # Arguments
+///
+/// * `text` - the text to check
+fn is_symbol(text: &str) -> bool {
+    match text.chars().next() {
+        Some(first) => !first.is_ascii_digit() && text.chars().all(is_symbol_char),
+        None => false
+    }
+}
+
+#[test]
+fn test_is_symbol() {
+    assert!(is_symbol("LOOP"));
+    assert!(is_symbol("Test_:123$"));
+    assert!(is_symbol("Main.loop"));
+    assert!(!is_symbol("1test"));
+    assert!(!is_symbol("a%"));
+    assert!(!is_symbol(""));
+}
+
+
+/// Splits an instruction into tokens: runs of symbol characters, and
+/// any other non-whitespace character on its own
+///
+/// # Arguments
+///
+/// * `command` - the stripped line
+fn tokenize(command: &str) -> Vec<&str> {
+    let mut tokens: Vec<&str> = Vec::new();
+    let mut start: Option<usize> = None;
+    for (idx, c) in command.char_indices() {
+        if is_symbol_char(c) {
+            start = start.or(Some(idx));
+            continue;
+        }
+        if let Some(name_start) = start.take() {
+            tokens.push(&command[name_start..idx]);
+        }
+        if !c.is_whitespace() {
+            tokens.push(&command[idx..idx + c.len_utf8()]);
+        }
+    }
+    if let Some(name_start) = start {
+        tokens.push(&command[name_start..]);
+    }
+    tokens
+}
+
+#[test]
+fn test_tokenize() {
+    assert_eq!(vec!["AM", "=", "M", "-", "1"], tokenize("AM=M-1"));
+    assert_eq!(vec!["D", ";", "JGT"], tokenize("D ; JGT"));
+    assert_eq!(vec!["(", "LOOP", ")", "x"], tokenize("(LOOP) x"));
+    assert_eq!(vec!["!", "M", "#"], tokenize("!M#"));
+}
+
+
+/// Returns the slice of `command` from the start of `first` to the end of `last`
+///
+/// # Arguments
+///
+/// * `command` - the stripped line
+/// * `first` - first token of the span
+/// * `last` - last token of the span
+fn span<'a>(command: &'a str, first: &str, last: &str) -> &'a str {
+    let start = first.as_ptr() as usize - command.as_ptr() as usize;
+    let end = last.as_ptr() as usize - command.as_ptr() as usize + last.len();
+    &command[start..end]
+}
+
+
+/// Returns the slice of the last character of `command`, empty if it is empty
+///
+/// # Arguments
+///
+/// * `command` - the stripped line
+fn last_char(command: &str) -> &str {
+    match command.char_indices().last() {
+        Some((idx, _)) => &command[idx..],
+        None => command
+    }
+}
+
+
+/// Parses the operand of an A instruction, e.g. `@LOOP`
+///
+/// # Arguments
+///
+/// * `command` - the stripped line, starting with `@`
+fn parse_a(command: &str) -> Result<Operand<'_>, LineError> {
+    let operand = &command[1..];
+    if let Some(value) = expr::parse_number(operand) {
+        return check_a_value(value, command, operand).map(Operand::Number);
+    }
+    if operand.contains(|c| "+-*/()'".contains(c)) {
+        return Ok(Operand::Expression(operand));
+    }
+
+    let end = operand.find(|c| !is_symbol_char(c)).unwrap_or(operand.len());
+    let (word, garbage) = (&operand[..end], operand[end..].trim_start());
+    if word.is_empty() {
+        Err(LineError::new(ErrorKind::InvalidACommand, command, command))
+    } else if !garbage.is_empty() {
+        Err(LineError::new(ErrorKind::InvalidACommand, command, garbage))
+    } else if !is_symbol(word) && !local::is_scoped(word) {
+        Err(LineError::new(ErrorKind::InvalidNumber, command, word))
+    } else {
+        Ok(Operand::Symbol(word))
+    }
+}
+
+#[test]
+fn test_parse_a() {
+    assert_eq!(Ok(Operand::Number(2)), parse_a("@2"));
+    assert_eq!(Ok(Operand::Number(0x4000)), parse_a("@0x4000"));
+    assert_eq!(Ok(Operand::Number(0b1010)), parse_a("@0b1010"));
+    assert_eq!(Ok(Operand::Number(65)), parse_a("@'A'"));
+    assert_eq!(Ok(Operand::Symbol("Test_:123$")), parse_a("@Test_:123$"));
+    assert_eq!(Ok(Operand::Expression("SCREEN+32*5")), parse_a("@SCREEN+32*5"));
+    assert_eq!(Ok(Operand::Number(43)), parse_a("@'+'"));
+    assert_eq!(Ok(Operand::Expression("'a'+1")), parse_a("@'a'+1"));
+    assert_eq!(Err(LineError { kind: ErrorKind::InvalidACommand, offset: 11, text: "%".to_string() }), parse_a("@Test_:123$%"));
+    assert_eq!(Err(LineError { kind: ErrorKind::InvalidACommand, offset: 6, text: "test".to_string() }), parse_a("@test test"));
+    assert_eq!(Ok(Operand::Symbol("1f")), parse_a("@1f"));
+    assert_eq!(Ok(Operand::Symbol(".loop")), parse_a("@.loop"));
+    assert_eq!(Err(LineError { kind: ErrorKind::InvalidNumber, offset: 1, text: "1x".to_string() }), parse_a("@1x"));
+    assert_eq!(Err(LineError { kind: ErrorKind::NumberOutOfRange, offset: 1, text: "40000".to_string() }), parse_a("@40000"));
+    assert_eq!(Err(LineError { kind: ErrorKind::NumberOutOfRange, offset: 1, text: "0xFFFF".to_string() }), parse_a("@0xFFFF"));
+    assert!(parse_a("@").is_err());
+}
+
+
+/// Parses a label declaration, e.g. `(LOOP)`, `(.loop)` or `(1)`
+///
+/// # Arguments
+///
+/// * `command` - the stripped line, starting with `(`
+fn parse_label(command: &str) -> Result<&str, LineError> {
+    let tokens = tokenize(command);
+    let symbol = match tokens.get(1) {
+        Some(symbol) if is_symbol(symbol) || local::is_anonymous(symbol) => symbol,
+        _ => return Err(LineError::new(ErrorKind::InvalidSymbol, command, command))
+    };
+    match tokens.get(2) {
+        Some(&")") => {},
+        Some(found) => return Err(LineError::new(ErrorKind::InvalidSymbol, command, span(command, symbol, found))),
+        None => return Err(LineError::new(ErrorKind::InvalidLCommand, command, command))
+    }
+    if let Some(garbage) = tokens.get(3) {
+        return Err(LineError::new(ErrorKind::InvalidLCommand, command, span(command, garbage, tokens[tokens.len() - 1])));
+    }
+    Ok(symbol)
+}
+
+#[test]
+fn test_parse_label() {
+    assert_eq!(Ok("test"), parse_label("(test)"));
+    assert_eq!(Ok("Test_:123$"), parse_label("(Test_:123$)"));
+    assert!(parse_label("(Test_:123$%)").is_err()); // % is invalid
+    assert_eq!(Err(LineError { kind: ErrorKind::InvalidLCommand, offset: 7, text: "test".to_string() }), parse_label("(test) test"));
+    assert_eq!(Ok(".loop"), parse_label("(.loop)"));
+    assert_eq!(Ok("1"), parse_label("(1)"));
+    assert!(parse_label("(1test)").is_err());
+    assert!(parse_label("(test").is_err());
+}
+
+
+/// Returns the canonical dest mnemonic for the registers in `text`,
+/// which may name A, M and D in any order, each at most once
+///
+/// # Arguments
+///
+/// * `text` - the dest as written, e.g. `DM`
+fn canonical_dest(text: &str) -> Option<&'static str> {
+    if let Some((dest, _)) = DESTS.get_key_value(text).filter(|(dest, _)| !dest.is_empty()) {
+        return Some(dest);
+    }
+    let mut sorted: Vec<char> = text.chars().collect();
+    sorted.sort_by_key(|register| "AMD".find(*register));
+    sorted.dedup();
+    if sorted.len() != text.len() || !sorted.iter().all(|register| "AMD".contains(*register)) {
+        return None; // repeated or unknown register
+    }
+    let canonical: String = sorted.into_iter().collect();
+    DESTS.get_key_value(canonical.as_str())
+        .filter(|(dest, _)| !dest.is_empty())
+        .map(|(dest, _)| *dest)
+}
+
+#[test]
+fn test_canonical_dest() {
+    assert_eq!(Some("MD"), canonical_dest("MD"));
+    assert_eq!(Some("MD"), canonical_dest("DM"));
+    assert_eq!(Some("AM"), canonical_dest("MA"));
+    assert_eq!(Some("AMD"), canonical_dest("DAM"));
+    assert_eq!(None, canonical_dest("MMD"));
+    assert_eq!(None, canonical_dest("MX"));
+    assert_eq!(None, canonical_dest(""));
+}
+
+
+/// Returns the canonical comp mnemonic for the comp tokens, trying the
+/// operands of a commutative operator (`+`, `&`, `|`) in both orders
+///
+/// # Arguments
+///
+/// * `tokens` - the comp tokens, e.g. `["M", "+", "D"]`
+fn canonical_comp(tokens: &[&str]) -> Option<&'static str> {
+    let text: String = tokens.concat();
+    if let Some((comp, _)) = COMPS.get_key_value(text.as_str()) {
+        return Some(comp);
+    }
+    match tokens {
+        [lhs, op, rhs] if ["+", "&", "|"].contains(op) => {
+            let swapped = format!("{}{}{}", rhs, op, lhs);
+            COMPS.get_key_value(swapped.as_str()).map(|(comp, _)| *comp)
+        },
+        _ => None
+    }
+}
+
+#[test]
+fn test_canonical_comp() {
+    assert_eq!(Some("D+M"), canonical_comp(&["D", "+", "M"]));
+    assert_eq!(Some("D+M"), canonical_comp(&["M", "+", "D"]));
+    assert_eq!(Some("D+A"), canonical_comp(&["A", "+", "D"]));
+    assert_eq!(Some("D&M"), canonical_comp(&["M", "&", "D"]));
+    assert_eq!(Some("D|A"), canonical_comp(&["A", "|", "D"]));
+    assert_eq!(Some("D+1"), canonical_comp(&["1", "+", "D"]));
+    assert_eq!(None, canonical_comp(&["M", "-", "D", "-", "1"]));
+    assert_eq!(None, canonical_comp(&["1", "-", "D"])); // not commutative
+    assert_eq!(None, canonical_comp(&["D", "+", "D"]));
+}
+
+
+/// Parses a C instruction, e.g. `AM=M-1` or `D;JGT`. Dest registers may
+/// be in any order and the operands of commutative comps may be swapped.
+///
+/// # Arguments
+///
+/// * `command` - the stripped line
+fn parse_c(command: &str) -> Result<Instruction<'static>, LineError> {
+    let tokens = tokenize(command);
+    let invalid = |token: &str| LineError::new(ErrorKind::InvalidCCommand, command, token);
+
+    // dest: a single name before '='
+    let (dest, rest) = match tokens.iter().position(|token| *token == "=") {
+        Some(0) => return Err(invalid(tokens[0])),
+        Some(1) => {
+            let dest = canonical_dest(tokens[0])
+                .ok_or_else(|| LineError::new(ErrorKind::UnknownDest, command, tokens[0]))?;
+            (dest, &tokens[2..])
+        },
+        Some(idx) => return Err(LineError::new(ErrorKind::UnknownDest, command, span(command, tokens[0], tokens[idx - 1]))),
+        None => ("", &tokens[..])
+    };
+
+    // comp: everything up to ';'
+    let (comp_tokens, jump_tokens) = match rest.iter().position(|token| *token == ";") {
+        Some(idx) => (&rest[..idx], Some(&rest[idx + 1..])),
+        None => (rest, None)
+    };
+    if let Some(token) = comp_tokens.iter().find(|token| **token == "=") {
+        return Err(invalid(token));
+    }
+    let comp = match (comp_tokens.first(), comp_tokens.last()) {
+        (Some(first), Some(last)) => {
+            // a comp written the canonical way needs no rearranging
+            let text = span(command, first, last);
+            match COMPS.get_key_value(text) {
+                Some((comp, _)) => *comp,
+                None => canonical_comp(comp_tokens)
+                    .ok_or_else(|| LineError::new(ErrorKind::UnknownComp, command, text))?
+            }
+        },
+        _ => return Err(invalid(rest.first().copied().unwrap_or_else(|| last_char(command))))
+    };
+
+    // jump: a single name after ';'
+    let jump = match jump_tokens {
+        None => "",
+        Some([]) => return Err(invalid(last_char(command))),
+        Some([jump, garbage @ ..]) => {
+            if let (Some(first), Some(last)) = (garbage.first(), garbage.last()) {
+                return Err(invalid(span(command, first, last)));
+            }
+            *JUMPS.get_key_value(*jump)
+                .filter(|(jump, _)| !jump.is_empty())
+                .ok_or_else(|| LineError::new(ErrorKind::UnknownJump, command, jump))?.0
+        }
+    };
+
+    Ok(Instruction::C { dest, comp, jump })
+}
+
+#[test]
+fn test_parse_c() {
+    let c = |dest, comp, jump| Ok(Instruction::C { dest, comp, jump });
+    assert_eq!(c("M", "D", "JMP"), parse_c("M=D;JMP"));
+    assert_eq!(c("", "D", "JGT"), parse_c("D;JGT"));
+    assert_eq!(c("", "D", ""), parse_c("D"));
+    assert_eq!(c("M", "D+M", ""), parse_c("M=D+M"));
+    assert_eq!(c("AM", "M-1", ""), parse_c("AM = M-1"));
+    assert_eq!(c("", "0", "JMP"), parse_c("0; JMP"));
+    assert_eq!(c("D", "-1", ""), parse_c("D=-1"));
+    assert_eq!(c("M", "D+M", ""), parse_c("M=M+D"));
+    assert_eq!(c("AMD", "D+1", ""), parse_c("AMD = D + 1"));
+    assert_eq!(c("MD", "D&M", "JEQ"), parse_c("DM=M&D;JEQ"));
+
+    let error = |kind, offset, text: &str| Err(LineError { kind, offset, text: text.to_string() });
+    assert_eq!(error(ErrorKind::UnknownDest, 0, "MMD"), parse_c("MMD=D"));
+    assert_eq!(error(ErrorKind::UnknownComp, 2, "Q"), parse_c("M=Q"));
+    assert_eq!(error(ErrorKind::UnknownComp, 2, "D+D"), parse_c("M=D+D"));
+    assert_eq!(error(ErrorKind::UnknownComp, 2, "1 - D"), parse_c("D=1 - D"));
+    assert_eq!(error(ErrorKind::UnknownJump, 2, "JGQ"), parse_c("D;JGQ"));
+    assert_eq!(error(ErrorKind::InvalidCCommand, 6, "x y"), parse_c("0;JMP x y"));
+    assert_eq!(error(ErrorKind::InvalidCCommand, 3, "="), parse_c("M=D=A"));
+    assert_eq!(error(ErrorKind::InvalidCCommand, 2, ";"), parse_c("M=;JMP"));
+    assert_eq!(error(ErrorKind::InvalidCCommand, 1, "="), parse_c("M="));
+    assert_eq!(error(ErrorKind::InvalidCCommand, 1, ";"), parse_c("D;"));
+    assert_eq!(error(ErrorKind::InvalidCCommand, 0, "="), parse_c("=D"));
+    assert_eq!(error(ErrorKind::InvalidCCommand, 0, ";"), parse_c(";é"));
+    assert_eq!(error(ErrorKind::InvalidCCommand, 0, "="), parse_c("=é"));
+    assert!(parse_c("#").is_err());
+}
+
+
+/// Parses a stripped, non-empty line of Hack assembly into an instruction
+///
+/// # Arguments
+///
+/// * `command` - the stripped line
+pub(crate) fn parse_instruction(command: &str) -> Result<Instruction<'_>, LineError> {
+    if command.starts_with('@') {
+        parse_a(command).map(Instruction::A)
+    } else if command.starts_with('(') {
+        parse_label(command).map(Instruction::Label)
+    } else {
+        parse_c(command)
+    }
+}
+
+#[test]
+fn test_parse_instruction() {
+    assert_eq!(Ok(Instruction::A(Operand::Symbol("i"))), parse_instruction("@i"));
+    assert_eq!(Ok(Instruction::Label("LOOP")), parse_instruction("(LOOP)"));
+    assert_eq!(Ok(Instruction::C { dest: "", comp: "0", jump: "JMP" }), parse_instruction("0;JMP"));
+    assert!(parse_instruction("M=D x").is_err());
+}
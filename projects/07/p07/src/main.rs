@@ -1,749 +1,92 @@
 // VM Translator for the Nand2Tetris Hack Computer
 // Author: Leo Robinovitch
+//
+// Translates a single .vm file, using the translator library of project 08
 
-use std::fs::File;
-use std::path::Path;
-use std::io::BufReader;
-use std::io::prelude::*;
 use std::env;
-use std::collections::HashMap;
-
-use regex::Regex;
-#[macro_use]
-extern crate lazy_static;
-
-// create an enum type called CommandType
-// that implements the Debug, etc. traits
-//     (printing with {:?} tells type)
-#[derive(PartialEq, Eq, Debug)]
-enum CommandType {
-    CArithmetic,
-    CPush,
-    CPop,
-    CLabel,
-    CGoTo,
-    CIf,
-    CFunction,
-    CReturn,
-    CCall
-}
-
-/// Returns a String of the file contents at path
-/// Note: path is referenced from the root directory of the project
-/// 
-/// # Arguments
-/// 
-/// * `path` - A std::path::Path that contains the input file path
-/// * `extension` - required extension for file
-fn get_file_contents(path: &Path, extension: &str) -> String { // takes reference to str, "read only"
-
-    assert_eq!(path.extension().unwrap(), extension);
-
-    let file = File::open(path).expect("Failed to open file");
-    let mut buf_reader = BufReader::new(file); // buffer reader for file
-    let mut contents = String::new(); // mutable string variable for contents
-    buf_reader.read_to_string(&mut contents) // mutable reference to contents
-        .expect("Error reading to string");
-    contents // returns contents
-}
-
-#[test]
-fn test_get_file_contents() {
-    // no test implemented
-}
-
-
-/// Create and return writable file based on path
-/// 
-/// # Arguments
-/// 
-/// * `path`
-fn create_file(path: &Path) -> File {
-    File::create(&path).unwrap()
-}
-
-#[test]
-fn test_create_file () {
-    // not implemented
-}
-
-
-/// Write line to file
-/// 
-/// # Arguments
-/// 
-/// * `file` - writable file
-/// * `line` - line to write to file
-fn write_to_file(mut file: &File, line: String) {
-    // Write the `LOREM_IPSUM` string to `file`, returns `io::Result<()>`
-    file.write_all(format!("{}\n", line).as_bytes()).expect("Failed to write line to file!");
-}
-
-
-/// Parse command line arguments and return input file
-/// contents and output file to write to
-fn parse_args() -> (String, File, String, String) {
-    // get user args
-    let args: Vec<String> = env::args().collect();
-
-    // check user args
-    if args.len() < 2 {
-        println!("Missing required argument!");
-        println!("Usage: cargo run FILENAME");
-        panic!();
-    };
-
-    let in_path_str = &args[1];
-    let in_path = Path::new(in_path_str);
-    let out_path_str = args[1].replace(".vm", ".asm");
-    let out_path = Path::new(&out_path_str);
-
-    let file_contents = get_file_contents(in_path, "vm");
-    let output_file = create_file(out_path);
-
-    (file_contents, output_file, in_path_str.to_string(), out_path_str.to_string())
-}
-
-#[test]
-fn test_parse_args() {
-    // not implemented
-}
-
-
-/// Returns a cleaned string slice after removing comments and white space
-/// 
-/// # Arguments
-/// 
-/// * `line` - the raw line
-fn remove_comments(line: &str) -> &str {
-    
-    // find the index where comments begin on the line
-    let idx_comment = match line.find("//") {
-        Some(idx) => idx,
-        _ => line.len()
-    };
-
-    // return a reference to the reduced str with no start/end whitespace
-    // note that memory contents are the same, just pointer and/or len changed
-    &line[0..idx_comment].trim()
-}
-
-#[test]
-fn test_stripped_line() {
-    assert_eq!("", remove_comments(""));
-    assert_eq!("", remove_comments("    "));
-    assert_eq!("", remove_comments("//   "));
-    assert_eq!("nand2tetris", remove_comments("nand2tetris   // is so cool"));
-    assert_eq!("nand2tetris is so cool", remove_comments("nand2tetris is so cool // eh?"));
-}
-
-
-/// Checks if VM code line is one of the 9 possible
-/// CArithmetic commands
-/// 
-/// # Arguments
-/// 
-/// * `line` - string slice that holds possible arithmetic command
-fn is_arithmetic(line: &str) -> bool {
-    lazy_static! { // lazy_static ensures compilation only happens once
-        static ref RE : Regex = Regex::new(
-                r"^(add|sub|neg|eq|gt|lt|and|or|not)$"
-            ).unwrap();
-    };
-
-    RE.captures(line).is_some()
-}
-
-#[test]
-fn test_is_arithmetic() {
-    assert_eq!(true, is_arithmetic("add"));
-    assert_eq!(true, is_arithmetic("sub"));
-    assert_eq!(true, is_arithmetic("neg"));
-    assert_eq!(true, is_arithmetic("eq"));
-    assert_eq!(true, is_arithmetic("gt"));
-    assert_eq!(true, is_arithmetic("lt"));
-    assert_eq!(true, is_arithmetic("and"));
-    assert_eq!(true, is_arithmetic("or"));
-    assert_eq!(true, is_arithmetic("not"));
-    assert_eq!(false, is_arithmetic("butt"));
-    assert_eq!(false, is_arithmetic("add sub"));
-}
-
-/// Gets command type for VM code line
-/// 
-/// # Arguments
-/// 
-/// * `line` - string slice that holds the current line
-fn get_command_type(line: &str) -> CommandType {
-    if is_arithmetic(line) {
-        CommandType::CArithmetic
-    } else if &line[0..4] == "push" {
-        CommandType::CPush
-    } else if &line[0..3] == "pop" {
-        CommandType::CPop
-    // to be extended
-    } else {
-        panic!();
-    }
-}
-
-#[test]
-fn test_get_command_type() {
-    assert_eq!(CommandType::CPush, get_command_type("push constant 1"));
-    assert_eq!(CommandType::CPop, get_command_type("pop constant 1"));
-    assert_eq!(CommandType::CArithmetic, get_command_type("eq"));
-}
+use std::fs;
+use std::path::Path;
+use std::process;
 
+use p08::{out_path, translate, Options, Source, TranslateError};
 
-/// Writes assembly code for push and pop commands
-/// 
+/// Parse command line arguments and return the input path, if there is
+/// exactly one
+///
 /// # Arguments
-/// 
-/// * `file` - output file
-/// * `file_name` - input filename, used for static vars
-/// * `line` - push/pop command
-/// * `command_type` - push or pop
-/// * `segment` - segment, e.g. "constant", "local", etc.
-/// * `index` - index for push/pop
-fn write_push_pop(file: &File, file_name: &String, line: &str, command_type: CommandType, segment: &str, index: i32) {
-    match segment {
-        "constant" => {
-            match command_type {
-                // in constant segment, pop should not be implemented
-                CommandType::CPop => {
-                    panic!("pop constant command detected!")
-                    // let asm_code = format!("// {line}\n\
-                    //         @SP\n\
-                    //         AM=M-1\n\
-                    //         D=M\n\
-                    //         @{index}\n\
-                    //         M=D", line=line, index=index);
-                    // write_to_file(file, asm_code)
-                },
-                // in constant segment, index is treated as value to push on to stack
-                CommandType::CPush => {
-                    let asm_code = format!("// {line}\n\
-                        @{index}\n\
-                        D=A\n\
-                        @SP\n\
-                        A=M\n\
-                        M=D\n\
-                        @SP\n\
-                        M=M+1", line=line, index=index);
-                    write_to_file(file, asm_code)
-                },
-                _ => panic!("Non CPush or CPop CommandType found in write_push_pop!")
-            }
-        },
-        "local" => {
-            match command_type {
-                // in local segment, pop puts top of stack at index in to local memory
-                CommandType::CPop => {
-                    let asm_code = format!("// {line}\n\
-                        @{index}\n\
-                        D=A\n\
-                        @LCL\n\
-                        A=M\n\
-                        D=D+A\n\
-                        @R13\n\
-                        M=D\n\
-                        @SP\n\
-                        AM=M-1\n\
-                        D=M\n\
-                        @R13\n\
-                        A=M\n\
-                        M=D", line=line, index=index);
-                    write_to_file(file, asm_code)
-                },
-                // in local segment, push puts index in to local memory on to stack
-                CommandType::CPush => {
-                    let asm_code = format!("// {line}\n\
-                        @{index}\n\
-                        D=A\n\
-                        @LCL\n\
-                        A=M\n\
-                        A=A+D\n\
-                        D=M\n\
-                        @SP\n\
-                        A=M\n\
-                        M=D\n\
-                        @SP\n\
-                        M=M+1", line=line, index=index);
-                    write_to_file(file, asm_code)
-                },
-                _ => panic!("Non CPush or CPop CommandType found in write_push_pop!")
-            }
-        },
-        "argument" => {
-            match command_type {
-                // in argument segment, pop puts top of stack at index in to argument memory
-                CommandType::CPop => {
-                    let asm_code = format!("// {line}\n\
-                        @{index}\n\
-                        D=A\n\
-                        @ARG\n\
-                        A=M\n\
-                        D=D+A\n\
-                        @R13\n\
-                        M=D\n\
-                        @SP\n\
-                        AM=M-1\n\
-                        D=M\n\
-                        @R13\n\
-                        A=M\n\
-                        M=D", line=line, index=index);
-                    write_to_file(file, asm_code)
-                },
-                // in argument segment, push puts index in to argument memory on to stack
-                CommandType::CPush => {
-                    let asm_code = format!("// {line}\n\
-                        @{index}\n\
-                        D=A\n\
-                        @ARG\n\
-                        A=M\n\
-                        A=A+D\n\
-                        D=M\n\
-                        @SP\n\
-                        A=M\n\
-                        M=D\n\
-                        @SP\n\
-                        M=M+1", line=line, index=index);
-                    write_to_file(file, asm_code)
-                },
-                _ => panic!("Non CPush or CPop CommandType found in write_push_pop!")
-            }
-        },
-        "this" => {
-            match command_type {
-                // in this segment, pop puts top of stack at index in to this memory
-                CommandType::CPop => {
-                    let asm_code = format!("// {line}\n\
-                        @{index}\n\
-                        D=A\n\
-                        @THIS\n\
-                        A=M\n\
-                        D=D+A\n\
-                        @R13\n\
-                        M=D\n\
-                        @SP\n\
-                        AM=M-1\n\
-                        D=M\n\
-                        @R13\n\
-                        A=M\n\
-                        M=D", line=line, index=index);
-                    write_to_file(file, asm_code)
-                },
-                // in this segment, push puts index in to this memory on to stack
-                CommandType::CPush => {
-                    let asm_code = format!("// {line}\n\
-                        @{index}\n\
-                        D=A\n\
-                        @THIS\n\
-                        A=M\n\
-                        A=A+D\n\
-                        D=M\n\
-                        @SP\n\
-                        A=M\n\
-                        M=D\n\
-                        @SP\n\
-                        M=M+1", line=line, index=index);
-                    write_to_file(file, asm_code)
-                },
-                _ => panic!("Non CPush or CPop CommandType found in write_push_pop!")
-            }
-        },
-        "that" => {
-            match command_type {
-                // in that segment, pop puts top of stack at index in to that memory
-                CommandType::CPop => {
-                    let asm_code = format!("// {line}\n\
-                        @{index}\n\
-                        D=A\n\
-                        @THAT\n\
-                        A=M\n\
-                        D=D+A\n\
-                        @R13\n\
-                        M=D\n\
-                        @SP\n\
-                        AM=M-1\n\
-                        D=M\n\
-                        @R13\n\
-                        A=M\n\
-                        M=D", line=line, index=index);
-                    write_to_file(file, asm_code)
-                },
-                // in that segment, push puts index in to that memory on to stack
-                CommandType::CPush => {
-                    let asm_code = format!("// {line}\n\
-                        @{index}\n\
-                        D=A\n\
-                        @THAT\n\
-                        A=M\n\
-                        A=A+D\n\
-                        D=M\n\
-                        @SP\n\
-                        A=M\n\
-                        M=D\n\
-                        @SP\n\
-                        M=M+1", line=line, index=index);
-                    write_to_file(file, asm_code)
-                },
-                _ => panic!("Non CPush or CPop CommandType found in write_push_pop!")
-            }
-        },
-        "temp" => {
-            match command_type {
-                // in temp segment, pop puts top of stack at index in to temp memory
-                CommandType::CPop => {
-                    let asm_code = format!("// {line}\n\
-                        @{index}\n\
-                        D=A\n\
-                        @5\n\
-                        D=D+A\n\
-                        @R13\n\
-                        M=D\n\
-                        @SP\n\
-                        AM=M-1\n\
-                        D=M\n\
-                        @R13\n\
-                        A=M\n\
-                        M=D", line=line, index=index);
-                    write_to_file(file, asm_code)
-                },
-                // in temp segment, push puts index in to temp memory on to stack
-                CommandType::CPush => {
-                    let asm_code = format!("// {line}\n\
-                        @{index}\n\
-                        D=A\n\
-                        @5\n\
-                        A=A+D\n\
-                        D=M\n\
-                        @SP\n\
-                        A=M\n\
-                        M=D\n\
-                        @SP\n\
-                        M=M+1", line=line, index=index);
-                    write_to_file(file, asm_code)
-                },
-                _ => panic!("Non CPush or CPop CommandType found in write_push_pop!")
-            }
-        },
-        "pointer" => {
-            match command_type {
-                // in pointer segment, pop puts top of stack at index in to pointer memory
-                CommandType::CPop => {
-                    let asm_code = format!("// {line}\n\
-                        @{index}\n\
-                        D=A\n\
-                        @3\n\
-                        D=D+A\n\
-                        @R13\n\
-                        M=D\n\
-                        @SP\n\
-                        AM=M-1\n\
-                        D=M\n\
-                        @R13\n\
-                        A=M\n\
-                        M=D", line=line, index=index);
-                    write_to_file(file, asm_code)
-                },
-                // in pointer segment, push puts index in to pointer memory on to stack
-                CommandType::CPush => {
-                    let asm_code = format!("// {line}\n\
-                        @{index}\n\
-                        D=A\n\
-                        @3\n\
-                        A=A+D\n\
-                        D=M\n\
-                        @SP\n\
-                        A=M\n\
-                        M=D\n\
-                        @SP\n\
-                        M=M+1", line=line, index=index);
-                    write_to_file(file, asm_code)
-                },
-                _ => panic!("Non CPush or CPop CommandType found in write_push_pop!")
-            }
-        },
-        "static" => {
-            match command_type {
-                // in static segment, pop puts top of stack at index in to static memory
-                CommandType::CPop => {
-                    let asm_code = format!("// {line}\n\
-                        @SP\n\
-                        AM=M-1\n\
-                        D=M\n\
-                        @{file_name}.{index}\n\
-                        M=D", line=line, file_name=file_name, index=index);
-                    write_to_file(file, asm_code)
-                },
-                // in static segment, push puts index in to static memory on to stack
-                CommandType::CPush => {
-                    let asm_code = format!("// {line}\n\
-                        @{file_name}.{index}\n\
-                        D=M\n\
-                        @SP\n\
-                        A=M\n\
-                        M=D\n\
-                        @SP\n\
-                        M=M+1", line=line, file_name=file_name, index=index);
-                    write_to_file(file, asm_code)
-                },
-                _ => panic!("Non CPush or CPop CommandType found in write_push_pop!")
-            }
-        },
-        _ => ()
+///
+/// * `args` - command line arguments, starting with the program name
+fn parse_args(args: &[String]) -> Option<String> {
+    match args {
+        [_, path] => Some(path.to_string()),
+        _ => None
     }
 }
 
 #[test]
-fn test_write_push_pop() {
-    // not implemented
-}
-
-
-/// Writes assembly code for push and pop commands to output file
-/// 
-/// # Arguments
-/// 
-/// * `file` - output file
-/// * `line` - input arithmetic command ("add", "sub", "eq", etc.)
-/// * `cmp_count` - count of previous comparison operations ("eq", "lt", and "gt").
-///     Used to mark jump and continue locations in these operations such that each
-///     label is unique.
-fn write_arithmetic(file: &File, line: &str, cmp_count: i32) -> i32 {
-
-    let mut new_cmp_count = cmp_count;
-
-    match line {
-        "add" => {
-            let asm_code = format!("// {line}\n\
-                @SP\n\
-                AM=M-1\n\
-                D=M\n\
-                @SP\n\
-                A=M-1\n\
-                M=M+D", line=line);
-            write_to_file(file, asm_code)
-        },
-        "sub" => {
-            let asm_code = format!("// {line}\n\
-                @SP\n\
-                AM=M-1\n\
-                D=M\n\
-                @SP\n\
-                A=M-1\n\
-                M=M-D", line=line);
-            write_to_file(file, asm_code)
-        },
-        "neg" => {
-            let asm_code = format!("// {line}\n\
-                @SP\n\
-                A=M-1\n\
-                M=-M", line=line);
-            write_to_file(file, asm_code)
-        },
-        "eq" => {
-            let asm_code = format!("// {line}\n\
-                @SP\n\
-                AM=M-1\n\
-                D=M\n\
-                @SP\n\
-                A=M-1\n\
-                D=M-D\n\
-                @EQUAL{cmp_count}\n\
-                D;JEQ\n\
-                @SP\n\
-                A=M-1\n\
-                M=0\n\
-                @CONTINUE{cmp_count}\n\
-                0;JMP\n\
-                (EQUAL{cmp_count})\n\
-                    @SP\n\
-                    A=M-1\n\
-                    M=-1\n\
-                (CONTINUE{cmp_count})", line=line, cmp_count=cmp_count);
-            new_cmp_count += 1;
-            write_to_file(file, asm_code)
-        },
-        "gt" => {
-            let asm_code = format!("// {line}\n\
-                @SP\n\
-                AM=M-1\n\
-                D=M\n\
-                @SP\n\
-                A=M-1\n\
-                D=M-D\n\
-                @GT{cmp_count}\n\
-                D;JGT\n\
-                @SP\n\
-                A=M-1\n\
-                M=0\n\
-                @CONTINUE{cmp_count}\n\
-                0;JMP\n\
-                (GT{cmp_count})\n\
-                    @SP\n\
-                    A=M-1\n\
-                    M=-1\n\
-                (CONTINUE{cmp_count})", line=line, cmp_count=cmp_count);
-            new_cmp_count += 1;
-            write_to_file(file, asm_code)
-        },
-        "lt" => {
-            let asm_code = format!("// {line}\n\
-                @SP\n\
-                AM=M-1\n\
-                D=M\n\
-                @SP\n\
-                A=M-1\n\
-                D=M-D\n\
-                @LT{cmp_count}\n\
-                D;JLT\n\
-                @SP\n\
-                A=M-1\n\
-                M=0\n\
-                @CONTINUE{cmp_count}\n\
-                0;JMP\n\
-                (LT{cmp_count})\n\
-                    @SP\n\
-                    A=M-1\n\
-                    M=-1\n\
-                (CONTINUE{cmp_count})", line=line, cmp_count=cmp_count);
-            new_cmp_count += 1;
-            write_to_file(file, asm_code)
-        },
-        "and" => {
-            let asm_code = format!("// {line}\n\
-                @SP\n\
-                M=M-1\n\
-                A=M\n\
-                D=M\n\
-                @SP\n\
-                A=M-1\n\
-                M=D&M", line=line);
-            write_to_file(file, asm_code)
-        },
-        "or" => {
-            let asm_code = format!("// {line}\n\
-                @SP\n\
-                M=M-1\n\
-                A=M\n\
-                D=M\n\
-                @SP\n\
-                A=M-1\n\
-                M=D|M", line=line);
-            write_to_file(file, asm_code)
-        },
-        "not" => {
-            let asm_code = format!("// {line}\n\
-                @SP\n\
-                A=M-1\n\
-                M=!M", line=line);
-            write_to_file(file, asm_code)
-        },
-        _ => {}
-    }
-    new_cmp_count
-}
-
-#[test]
-fn test_write_arithmetic() {
-    // not implemented
+fn test_parse_args() {
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+    assert_eq!(Some("StackTest.vm".to_string()), parse_args(&args(&["p07", "StackTest.vm"])));
+    assert_eq!(None, parse_args(&args(&["p07"])));
+    assert_eq!(None, parse_args(&args(&["p07", "a.vm", "b.vm"])));
 }
 
 
-/// Get segment and index from push/pop command
-/// 
+/// Translates the contents of a single .vm file, without bootstrap code
+///
 /// # Arguments
-/// 
-/// * `line` - push/pop command
-fn parse_push_pop(line: &str) -> (&str, i32) {
-    lazy_static! { // lazy_static ensures compilation only happens once
-        static ref RE : Regex = Regex::new(
-                r"^(push|pop)\s*(\w*)\s*(\d*)" // TO DO may need refining
-            ).unwrap();
-    };
-
-    let capture = RE.captures(line)
-        .expect("Invalid push/pop command!");
-
-    (capture.get(2).unwrap().as_str(),
-    capture.get(3).unwrap().as_str().parse::<i32>().unwrap())
+///
+/// * `in_path` - path of the file, used in comments, errors and static names
+/// * `contents` - contents of the file
+fn translate_file(in_path: &str, contents: String) -> Result<String, Vec<TranslateError>> {
+    let sources = [Source { name: in_path.to_string(), contents }];
+    translate(&sources, &Options::default())
 }
 
 #[test]
-fn test_parse_push_pop() {
-    assert_eq!(("static", 1), parse_push_pop("push static 1"));
-    assert_eq!(("local", 10), parse_push_pop("pop local 10"));
-    let result = std::panic::catch_unwind(|| parse_push_pop("eq")); // % is invalid
-    assert!(result.is_err());
-}
+fn test_translate_file() {
+    let asm_code = translate_file("dir/StaticTest.vm", "push constant 7\r\npop static 8\r\nadd\r\n".to_string()).unwrap();
+    assert!(!asm_code.contains("Bootstrap"));
+    assert!(asm_code.contains("// push constant 7\n@7\nD=A\n"));
+    assert!(asm_code.contains("// pop static 8\n@SP\nAM=M-1\nD=M\n@StaticTest.8\nM=D\n"));
 
+    // calls to functions of other files are left to the assembler
+    assert!(translate_file("Main.vm", "call Math.multiply 2\n".to_string()).is_ok());
 
-/// Get file name from path
-/// 
-/// # Arguments
-/// 
-/// * `path` - path
-fn get_file_name(path: &str) -> String {
-    match path.rfind('/') {
-        Some(idx_slash) => {
-            match path.rfind('.') {
-                Some(idx_dot) => path[idx_slash+1..idx_dot].to_string(),
-                _ => path.to_string()
-            }
-        },
-        _ => {
-            match path.rfind('.') {
-                Some(idx_dot) => path[..idx_dot].to_string(),
-                _ => path.to_string()
-            }
-        }
-    }
+    let errors = translate_file("Main.vm", "push constant 1\npop constant 1\n".to_string()).unwrap_err();
+    assert_eq!(vec!["Main.vm:2: cannot pop to the constant segment `pop constant 1`"],
+               errors.iter().map(|e| e.to_string()).collect::<Vec<String>>());
 }
 
-#[test]
-fn test_get_file_name() {
-    assert_eq!("test", get_file_name("/asdfasdf/asdfasdf/test.vm"));
-    assert_eq!("test", get_file_name("/test.vm"));
-    assert_eq!("test", get_file_name("test.vm"));
-    assert_eq!("test", get_file_name("test"));
-}
 
 /// ********************************
 /// ************* MAIN *************
 /// ********************************
-fn main () {
+fn main() {
 
-    let (file_contents, output_file, in_path, out_path) = parse_args();
-    let in_file_name = get_file_name(&in_path);
-
-    let mut cmp_count = 0;
-    for line in file_contents.lines() {
-        let clean_line = remove_comments(line);
-        if clean_line == "" { continue };
-        println!("Clean line: {}", clean_line);
-
-        let command_type = get_command_type(line);
-        println!("Command type: {:?}", command_type);
-
-        match command_type {
-            CommandType::CPush => {
-                let (segment, index) = parse_push_pop(clean_line);
-                write_push_pop(&output_file, &in_file_name, line, command_type, segment, index);
-            },
-            CommandType::CPop => {
-                let (segment, index) = parse_push_pop(clean_line);
-                write_push_pop(&output_file, &in_file_name, line, command_type, segment, index);
-            },
-            CommandType::CArithmetic => {
-                cmp_count = write_arithmetic(&output_file, line, cmp_count);
-            },
-            _ => ()
+    let args: Vec<String> = env::args().collect();
+    let in_path = parse_args(&args).unwrap_or_else(|| {
+        println!("Missing required argument!");
+        println!("Usage: cargo run FILENAME");
+        process::exit(1);
+    });
+    let contents = fs::read_to_string(&in_path).unwrap_or_else(|e| {
+        eprintln!("error: could not read {:?}: {}", in_path, e);
+        process::exit(1);
+    });
+
+    let asm_code = translate_file(&in_path, contents).unwrap_or_else(|errors| {
+        for error in &errors {
+            eprintln!("{}", error);
         }
-
-    }
-
+        eprintln!("error: could not translate {:?} due to {} previous error(s)", in_path, errors.len());
+        process::exit(1);
+    });
+
+    let out_path = out_path(Path::new(&in_path));
+    fs::write(&out_path, asm_code).unwrap_or_else(|e| {
+        eprintln!("error: could not write {:?}: {}", out_path, e);
+        process::exit(1);
+    });
     println!("\nTranslated {:?}\n        -> {:?}\n", in_path, out_path);
-
-}
\ No newline at end of file
+}
@@ -0,0 +1,107 @@
+// Whole-program checks of parsed .vm files, run before code generation
+// Author: Leo Robinovitch
+
+use std::collections::HashMap;
+
+use crate::codegen::ENTRY_FUNCTION;
+use crate::parser::VmCommand;
+use crate::{ErrorKind, Options, TranslateError, VmFile};
+
+
+/// Returns the file and line of every function definition, reporting
+/// functions defined more than once
+///
+/// # Arguments
+///
+/// * `files` - parsed .vm files
+/// * `errors` - errors found so far
+fn define_functions<'a>(files: &'a [VmFile], errors: &mut Vec<TranslateError>) -> HashMap<&'a str, (&'a str, usize)> {
+    let mut functions: HashMap<&str, (&str, usize)> = HashMap::new();
+    for file in files {
+        for (line, command) in &file.commands {
+            if let VmCommand::Function { name, .. } = command {
+                if let Some((first_file, first_line)) = functions.get(name.as_str()) {
+                    errors.push(TranslateError {
+                        kind: ErrorKind::DuplicateFunction,
+                        file: file.name.clone(),
+                        line: *line,
+                        text: format!("{} (first defined at {}:{})", name, first_file, first_line),
+                    });
+                } else {
+                    functions.insert(name, (&file.name, *line));
+                }
+            }
+        }
+    }
+    functions
+}
+
+
+/// Checks the parsed .vm files as one program, returning every error found:
+/// functions defined twice, a missing Sys.init when bootstrapping or pruning,
+/// and, when the files are the whole program, calls to undefined functions
+///
+/// # Arguments
+///
+/// * `files` - parsed .vm files
+/// * `options` - translator options
+pub fn check_program(files: &[VmFile], options: &Options) -> Vec<TranslateError> {
+    let mut errors: Vec<TranslateError> = Vec::new();
+    let functions = define_functions(files, &mut errors);
+
+    if (options.bootstrap || options.prune) && !functions.contains_key(ENTRY_FUNCTION) {
+        errors.push(TranslateError {
+            kind: ErrorKind::MissingSysInit,
+            file: String::new(),
+            line: 0,
+            text: ENTRY_FUNCTION.to_string(),
+        });
+    }
+
+    if options.whole_program {
+        for file in files {
+            for (line, command) in &file.commands {
+                match command {
+                    VmCommand::Call { name, .. } if !functions.contains_key(name.as_str()) => {
+                        errors.push(TranslateError {
+                            kind: ErrorKind::UndefinedFunction,
+                            file: file.name.clone(),
+                            line: *line,
+                            text: name.clone(),
+                        });
+                    },
+                    _ => ()
+                }
+            }
+        }
+    }
+    errors
+}
+
+#[test]
+fn test_check_program() {
+    use crate::{parse_source, Source};
+
+    let parse = |name: &str, contents: &str| {
+        parse_source(&Source { name: name.to_string(), contents: contents.to_string() }).unwrap()
+    };
+    let files = vec![
+        parse("Main.vm", "function Main.main 0\ncall Main.mian 0\ncall Math.multiply 2\nreturn\n"),
+        parse("Sys.vm", "function Sys.halt 0\ncall Main.main 0\nfunction Main.main 1\n"),
+    ];
+    let whole_program = Options { bootstrap: true, whole_program: true, ..Options::default() };
+    let errors = check_program(&files, &whole_program);
+    let found: Vec<(ErrorKind, &str, usize, &str)> = errors.iter()
+        .map(|e| (e.kind, e.file.as_str(), e.line, e.text.as_str()))
+        .collect();
+    assert_eq!(vec![
+        (ErrorKind::DuplicateFunction, "Sys.vm", 3, "Main.main (first defined at Main.vm:1)"),
+        (ErrorKind::MissingSysInit, "", 0, "Sys.init"),
+        (ErrorKind::UndefinedFunction, "Main.vm", 2, "Main.mian"),
+        (ErrorKind::UndefinedFunction, "Main.vm", 3, "Math.multiply"),
+    ], found);
+
+    // a single file may call functions of files translated separately
+    assert!(check_program(&files[..1], &Options::default()).is_empty());
+    assert_eq!("the program starts at a function that is not defined `Sys.init`", errors[1].to_string());
+}
@@ -0,0 +1,479 @@
+// Hack assembly code generation for parsed VM commands
+// Author: Leo Robinovitch
+
+use crate::parser::{Op, Segment, VmCommand};
+
+/// Hack code that pushes D on to the stack
+const PUSH_D: &str = "@SP\nA=M\nM=D\n@SP\nM=M+1";
+
+/// Function the bootstrap code calls
+pub const ENTRY_FUNCTION: &str = "Sys.init";
+
+/// Label of the shared call routine of compact code
+const CALL_ROUTINE: &str = "$$CALL";
+
+/// Label of the shared return routine of compact code
+const RETURN_ROUTINE: &str = "$$RETURN";
+
+
+/// Returns Hack code that computes the address of a segment entry into
+/// `dest`. Not for the constant and static segments, which have no base.
+///
+/// # Arguments
+///
+/// * `segment` - memory segment
+/// * `index` - index in the segment
+/// * `dest` - register the address is computed into, `A` or `D`
+fn segment_address(segment: Segment, index: u16, dest: &str) -> String {
+    let base = match segment {
+        Segment::Local => "@LCL\nA=M",
+        Segment::Argument => "@ARG\nA=M",
+        Segment::This => "@THIS\nA=M",
+        Segment::That => "@THAT\nA=M",
+        Segment::Temp => "@5",
+        Segment::Pointer => "@3",
+        Segment::Constant | Segment::Static => unreachable!("{} has no base address", segment.name())
+    };
+    format!("@{}\nD=A\n{}\n{}=D+A", index, base, dest)
+}
+
+
+/// Returns Hack code for a push command
+///
+/// # Arguments
+///
+/// * `file_name` - input file name, used for static variables
+/// * `segment` - memory segment
+/// * `index` - index in the segment, the value for the constant segment
+fn write_push(file_name: &str, segment: Segment, index: u16) -> String {
+    let load = match segment {
+        Segment::Constant => format!("@{}\nD=A", index),
+        Segment::Static => format!("@{}.{}\nD=M", file_name, index),
+        _ => format!("{}\nD=M", segment_address(segment, index, "A"))
+    };
+    format!("{}\n{}", load, PUSH_D)
+}
+
+#[test]
+fn test_write_push() {
+    assert_eq!("@7\nD=A\n@SP\nA=M\nM=D\n@SP\nM=M+1", write_push("Main", Segment::Constant, 7));
+    assert_eq!("@Main.3\nD=M\n@SP\nA=M\nM=D\n@SP\nM=M+1", write_push("Main", Segment::Static, 3));
+    assert_eq!("@2\nD=A\n@LCL\nA=M\nA=D+A\nD=M\n@SP\nA=M\nM=D\n@SP\nM=M+1", write_push("Main", Segment::Local, 2));
+    assert_eq!("@1\nD=A\n@3\nA=D+A\nD=M\n@SP\nA=M\nM=D\n@SP\nM=M+1", write_push("Main", Segment::Pointer, 1));
+}
+
+
+/// Returns Hack code for a pop command
+///
+/// # Arguments
+///
+/// * `file_name` - input file name, used for static variables
+/// * `segment` - memory segment, not constant
+/// * `index` - index in the segment
+fn write_pop(file_name: &str, segment: Segment, index: u16) -> String {
+    match segment {
+        Segment::Constant => unreachable!("pop constant is rejected by the parser"),
+        Segment::Static => format!("@SP\nAM=M-1\nD=M\n@{}.{}\nM=D", file_name, index),
+        _ => {
+            // the address is kept in R13 while the stack is popped
+            format!("{}\n@R13\nM=D\n@SP\nAM=M-1\nD=M\n@R13\nA=M\nM=D", segment_address(segment, index, "D"))
+        }
+    }
+}
+
+#[test]
+fn test_write_pop() {
+    assert_eq!("@SP\nAM=M-1\nD=M\n@Main.3\nM=D", write_pop("Main", Segment::Static, 3));
+    assert_eq!("@6\nD=A\n@5\nD=D+A\n@R13\nM=D\n@SP\nAM=M-1\nD=M\n@R13\nA=M\nM=D", write_pop("Main", Segment::Temp, 6));
+}
+
+#[test]
+fn test_write_push_pop() {
+    let push = "@SP\nA=M\nM=D\n@SP\nM=M+1";
+    let pop = "@R13\nM=D\n@SP\nAM=M-1\nD=M\n@R13\nA=M\nM=D";
+
+    // segments with a base pointer are addressed relative to it
+    for (segment, base) in &[(Segment::Local, "@LCL\nA=M"), (Segment::Argument, "@ARG\nA=M"),
+                             (Segment::This, "@THIS\nA=M"), (Segment::That, "@THAT\nA=M")] {
+        assert_eq!(format!("@4\nD=A\n{}\nA=D+A\nD=M\n{}", base, push), write_push("Main", *segment, 4));
+        assert_eq!(format!("@4\nD=A\n{}\nD=D+A\n{}", base, pop), write_pop("Main", *segment, 4));
+    }
+
+    // temp and pointer are fixed RAM blocks starting at 5 and 3
+    assert_eq!(format!("@7\nD=A\n@5\nA=D+A\nD=M\n{}", push), write_push("Main", Segment::Temp, 7));
+    assert_eq!(format!("@0\nD=A\n@3\nD=D+A\n{}", pop), write_pop("Main", Segment::Pointer, 0));
+    assert_eq!(format!("@1\nD=A\n@3\nD=D+A\n{}", pop), write_pop("Main", Segment::Pointer, 1));
+
+    // statics are named after the file, constants are loaded into D
+    assert_eq!(format!("@StaticTest.8\nD=M\n{}", push), write_push("StaticTest", Segment::Static, 8));
+    assert_eq!("@SP\nAM=M-1\nD=M\n@StaticTest.8\nM=D", write_pop("StaticTest", Segment::Static, 8));
+    assert_eq!(format!("@32767\nD=A\n{}", push), write_push("Main", Segment::Constant, 32767));
+    assert_eq!(format!("@0\nD=A\n{}", push), write_push("Main", Segment::Constant, 0));
+}
+
+
+/// Returns Hack code for a function declaration, which pushes a 0 for each local
+///
+/// # Arguments
+///
+/// * `name` - function name
+/// * `locals` - number of local variables
+fn write_function(name: &str, locals: u16) -> String {
+    let mut asm_code = format!("({})", name);
+    for _ in 0..locals {
+        asm_code.push_str("\n@SP\nA=M\nM=0\n@SP\nM=M+1");
+    }
+    asm_code
+}
+
+
+/// Returns Hack code that pushes the caller's LCL, ARG, THIS and THAT, then
+/// points ARG at the arguments and LCL at the top of the stack
+///
+/// # Arguments
+///
+/// * `args` - symbol or number that holds the number of arguments
+/// * `source` - `A` if `args` is the number itself, `M` if it is stored at `args`
+fn write_frame(args: &str, source: &str) -> String {
+    let mut asm_code = String::new();
+    for pointer in &["LCL", "ARG", "THIS", "THAT"] {
+        asm_code.push_str(&format!("// push {pointer}\n@{pointer}\nD=M\n{push}\n", pointer=pointer, push=PUSH_D));
+    }
+    asm_code.push_str(&format!("\
+        // ARG = SP - n - 5\n\
+        @SP\n\
+        D=M\n\
+        @{args}\n\
+        D=D-{source}\n\
+        @5\n\
+        D=D-A\n\
+        @ARG\n\
+        M=D\n\
+        // LCL = SP\n\
+        @SP\n\
+        D=M\n\
+        @LCL\n\
+        M=D", args=args, source=source));
+    asm_code
+}
+
+
+/// Returns Hack code for a return command
+fn write_return() -> String {
+    "// FRAME = LCL = M[R13]\n\
+    @LCL\n\
+    D=M // D = M[LCL]\n\
+    @R13\n\
+    M=D // M[R13] = M[LCL]\n\
+    // RET = *(FRAME-5) = M[R14]\n\
+    @5\n\
+    D=A // D = 5\n\
+    @R13\n\
+    A=M-D // A = LCL - 5\n\
+    D=M // D = M[LCL-5]\n\
+    @R14\n\
+    M=D // M[R14] = M[LCL-5]\n\
+    // *ARG = pop()\n\
+    @SP\n\
+    AM=M-1\n\
+    D=M // D = pop()\n\
+    @ARG\n\
+    A=M\n\
+    M=D\n\
+    // SP = ARG+1\n\
+    D=A+1\n\
+    @SP\n\
+    M=D // M[SP] = M[ARG] + 1\n\
+    // THAT = *(FRAME-1)\n\
+    @R13\n\
+    D=M // D = M[R13] = LCL\n\
+    @1\n\
+    A=D-A // A = LCL - 1\n\
+    D=M // D = M[LCL - 1]\n\
+    @THAT\n\
+    M=D\n\
+    // THIS = *(FRAME-2)\n\
+    @R13\n\
+    D=M // D = M[R13] = LCL\n\
+    @2\n\
+    A=D-A // A = LCL - 2\n\
+    D=M // D = M[LCL - 2]\n\
+    @THIS\n\
+    M=D\n\
+    // ARG = *(FRAME-3)\n\
+    @R13\n\
+    D=M // D = M[R13] = LCL\n\
+    @3\n\
+    A=D-A // A = LCL - 3\n\
+    D=M // D = M[LCL - 3]\n\
+    @ARG\n\
+    M=D\n\
+    // LCL = *(FRAME-4)\n\
+    @R13\n\
+    D=M // D = M[R13] = LCL\n\
+    @4\n\
+    A=D-A // A = LCL - 4\n\
+    D=M // D = M[LCL - 4]\n\
+    @LCL\n\
+    M=D\n\
+    // goto RET\n\
+    @R14\n\
+    A=M // A = M[R14] = RET\n\
+    0;JMP".to_string()
+}
+
+
+/// Generates Hack code for VM commands in order, numbering the labels
+/// that comparisons and calls need so that each is unique
+#[derive(Default)]
+pub struct CodeWriter {
+    /// count of previous comparisons (`eq`, `gt` and `lt`)
+    cmp_count: usize,
+    /// count of previous calls, for return address labels
+    call_count: usize,
+    /// function of the commands being written, which scopes their labels
+    function: Option<String>,
+    /// calls and returns jump to shared routines instead of being inlined
+    compact: bool,
+    /// whether a return command has been written
+    returned: bool,
+}
+
+impl CodeWriter {
+    /// Returns a CodeWriter that has written nothing yet
+    pub fn new() -> CodeWriter {
+        CodeWriter::default()
+    }
+
+    /// Returns a CodeWriter of compact code, whose calls and returns jump to
+    /// the shared routines of `routines` instead of being inlined
+    pub fn compact() -> CodeWriter {
+        CodeWriter { compact: true, ..CodeWriter::default() }
+    }
+
+    /// Starts a new .vm file, whose commands are outside any function
+    /// until its first function command
+    pub fn start_file(&mut self) {
+        self.function = None;
+    }
+
+    /// Returns the Hack label for a VM label, `FunctionName$label` inside a function
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - label as written in the VM command
+    fn label(&self, label: &str) -> String {
+        match &self.function {
+            Some(function) => format!("{}${}", function, label),
+            None => label.to_string()
+        }
+    }
+
+    /// Returns Hack code that sets the stack pointer to 256 and calls Sys.init
+    pub fn bootstrap(&mut self) -> String {
+        let call = VmCommand::Call { name: ENTRY_FUNCTION.to_string(), args: 0 };
+        format!("// Bootstrap\n\n@256\nD=A\n@SP\nM=D\n{}", self.write("", &call))
+    }
+
+    /// Returns the Hack code for a VM command, after a comment with the command
+    ///
+    /// # Arguments
+    ///
+    /// * `file_name` - input file name, used for static variables
+    /// * `command` - the command
+    pub fn write(&mut self, file_name: &str, command: &VmCommand) -> String {
+        let asm_code = match command {
+            VmCommand::Push(segment, index) => write_push(file_name, *segment, *index),
+            VmCommand::Pop(segment, index) => write_pop(file_name, *segment, *index),
+            VmCommand::Arith(op) => self.write_arithmetic(*op),
+            VmCommand::Label(label) => format!("({})", self.label(label)),
+            VmCommand::Goto(label) => format!("@{}\n0;JMP", self.label(label)),
+            VmCommand::IfGoto(label) => format!("@SP\nAM=M-1\nD=M\n@{}\nD;JNE", self.label(label)),
+            VmCommand::Function { name, locals } => {
+                self.function = Some(name.clone());
+                write_function(name, *locals)
+            },
+            VmCommand::Call { name, args } => self.write_call(name, *args),
+            VmCommand::Return if self.compact => {
+                self.returned = true;
+                format!("@{}\n0;JMP", RETURN_ROUTINE)
+            },
+            VmCommand::Return => write_return(),
+        };
+        format!("// {}\n{}", command, asm_code)
+    }
+
+    /// Returns Hack code for an arithmetic or logical command
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - the command
+    fn write_arithmetic(&mut self, op: Op) -> String {
+        let (comp, jump) = match op {
+            Op::Add => ("D+M", None),
+            Op::Sub => ("M-D", None),
+            Op::And => ("D&M", None),
+            Op::Or => ("D|M", None),
+            Op::Neg => return "@SP\nA=M-1\nM=-M".to_string(),
+            Op::Not => return "@SP\nA=M-1\nM=!M".to_string(),
+            Op::Eq => ("M-D", Some(("EQUAL", "JEQ"))),
+            Op::Gt => ("M-D", Some(("GT", "JGT"))),
+            Op::Lt => ("M-D", Some(("LT", "JLT"))),
+        };
+
+        // pop y into D, leaving A at x
+        let asm_code = "@SP\nAM=M-1\nD=M\n@SP\nA=M-1";
+        match jump {
+            None => format!("{}\nM={}", asm_code, comp),
+            Some((label, jump)) => {
+                let count = self.cmp_count;
+                self.cmp_count += 1;
+                format!("{asm_code}\n\
+                    D={comp}\n\
+                    @{label}{count}\n\
+                    D;{jump}\n\
+                    @SP\n\
+                    A=M-1\n\
+                    M=0\n\
+                    @CONTINUE{count}\n\
+                    0;JMP\n\
+                    ({label}{count})\n\
+                    @SP\n\
+                    A=M-1\n\
+                    M=-1\n\
+                    (CONTINUE{count})", asm_code=asm_code, comp=comp, label=label, jump=jump, count=count)
+            }
+        }
+    }
+
+    /// Returns Hack code for a call command, which saves the caller's frame
+    /// and jumps to the function, or in compact code sets R13 to the number
+    /// of arguments, R14 to the function and R15 to the return address and
+    /// jumps to the shared call routine
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - function name
+    /// * `args` - number of arguments pushed before the call
+    fn write_call(&mut self, name: &str, args: u16) -> String {
+        let count = self.call_count;
+        self.call_count += 1;
+
+        if self.compact {
+            return format!("@{args}\n\
+                D=A\n\
+                @R13\n\
+                M=D\n\
+                @{name}\n\
+                D=A\n\
+                @R14\n\
+                M=D\n\
+                @returnaddr{count}\n\
+                D=A\n\
+                @R15\n\
+                M=D\n\
+                @{call}\n\
+                0;JMP\n\
+                (returnaddr{count})", args=args, name=name, count=count, call=CALL_ROUTINE);
+        }
+        format!("// push returnaddr\n@returnaddr{count}\nD=A\n{push}\n{frame}\n\
+            // goto f\n\
+            @{name}\n\
+            0;JMP\n\
+            // declare (returnaddr)\n\
+            (returnaddr{count})", count=count, push=PUSH_D, frame=write_frame(&args.to_string(), "A"), name=name)
+    }
+
+    /// Returns the Hack code of the shared call and return routines that
+    /// compact code jumped to, leaving out those it never used
+    pub fn routines(&self) -> String {
+        let mut asm_code = String::new();
+        if self.compact && self.call_count > 0 {
+            asm_code.push_str(&format!("// Call routine: push R15 and the caller's frame, then goto R14 with R13 arguments\n\
+                ({call})\n\
+                @R15\n\
+                D=M\n\
+                {push}\n\
+                {frame}\n\
+                @R14\n\
+                A=M\n\
+                0;JMP\n", call=CALL_ROUTINE, push=PUSH_D, frame=write_frame("R13", "M")));
+        }
+        if self.compact && self.returned {
+            asm_code.push_str(&format!("// Return routine\n({})\n{}\n", RETURN_ROUTINE, write_return()));
+        }
+        asm_code
+    }
+}
+
+#[test]
+fn test_code_writer() {
+    let mut writer = CodeWriter::new();
+    assert_eq!("// add\n@SP\nAM=M-1\nD=M\n@SP\nA=M-1\nM=D+M", writer.write("Main", &VmCommand::Arith(Op::Add)));
+    assert_eq!("// goto END\n@END\n0;JMP", writer.write("Main", &VmCommand::Goto("END".to_string())));
+
+    // labels of comparisons and calls are numbered in order
+    let eq = writer.write("Main", &VmCommand::Arith(Op::Eq));
+    assert!(eq.contains("@EQUAL0\nD;JEQ") && eq.ends_with("(CONTINUE0)"));
+    assert!(writer.write("Main", &VmCommand::Arith(Op::Lt)).ends_with("(CONTINUE1)"));
+    assert!(writer.bootstrap().ends_with("@Sys.init\n0;JMP\n// declare (returnaddr)\n(returnaddr0)"));
+    let call = writer.write("Main", &VmCommand::Call { name: "Main.f".to_string(), args: 2 });
+    assert!(call.starts_with("// call Main.f 2\n// push returnaddr\n@returnaddr1\n"));
+    assert!(call.contains("@2\nD=D-A\n@5\nD=D-A\n@ARG\nM=D"));
+
+    let function = writer.write("Main", &VmCommand::Function { name: "Main.f".to_string(), locals: 2 });
+    assert_eq!(2, function.matches("M=0").count());
+    assert_eq!("", writer.routines());
+}
+
+#[test]
+fn test_write_arithmetic() {
+    let mut writer = CodeWriter::new();
+    let pop_y = "@SP\nAM=M-1\nD=M\n@SP\nA=M-1";
+    assert_eq!(format!("{}\nM=D+M", pop_y), writer.write_arithmetic(Op::Add));
+    assert_eq!(format!("{}\nM=M-D", pop_y), writer.write_arithmetic(Op::Sub));
+    assert_eq!(format!("{}\nM=D&M", pop_y), writer.write_arithmetic(Op::And));
+    assert_eq!(format!("{}\nM=D|M", pop_y), writer.write_arithmetic(Op::Or));
+    assert_eq!("@SP\nA=M-1\nM=-M", writer.write_arithmetic(Op::Neg));
+    assert_eq!("@SP\nA=M-1\nM=!M", writer.write_arithmetic(Op::Not));
+
+    // comparisons set x to -1 (true) or 0 (false), with unique labels
+    assert_eq!(format!("{}\nD=M-D\n@EQUAL0\nD;JEQ\n@SP\nA=M-1\nM=0\n@CONTINUE0\n0;JMP\n\
+                        (EQUAL0)\n@SP\nA=M-1\nM=-1\n(CONTINUE0)", pop_y), writer.write_arithmetic(Op::Eq));
+    let gt = writer.write_arithmetic(Op::Gt);
+    assert!(gt.contains("@GT1\nD;JGT\n") && gt.contains("\n(GT1)\n") && gt.ends_with("(CONTINUE1)"));
+    let lt = writer.write_arithmetic(Op::Lt);
+    assert!(lt.contains("@LT2\nD;JLT\n") && lt.contains("\n(LT2)\n") && lt.ends_with("(CONTINUE2)"));
+    assert!(writer.write_arithmetic(Op::Add).ends_with("M=D+M"));
+    assert!(writer.write_arithmetic(Op::Eq).ends_with("(CONTINUE3)"));
+}
+
+#[test]
+fn test_code_writer_compact() {
+    // calls and returns jump to routines written once at the end
+    let mut writer = CodeWriter::compact();
+    assert_eq!("", writer.routines());
+    assert_eq!("// call Main.f 2\n@2\nD=A\n@R13\nM=D\n@Main.f\nD=A\n@R14\nM=D\n@returnaddr0\nD=A\n@R15\nM=D\n@$$CALL\n0;JMP\n(returnaddr0)",
+               writer.write("Main", &VmCommand::Call { name: "Main.f".to_string(), args: 2 }));
+    assert_eq!("// return\n@$$RETURN\n0;JMP", writer.write("Main", &VmCommand::Return));
+
+    let routines = writer.routines();
+    assert!(routines.contains("($$CALL)\n@R15\nD=M\n@SP\nA=M\nM=D\n@SP\nM=M+1\n// push LCL\n"));
+    assert!(routines.contains("@SP\nD=M\n@R13\nD=D-M\n@5\nD=D-A\n@ARG\nM=D\n"));
+    assert!(routines.contains("@R14\nA=M\n0;JMP\n// Return routine\n($$RETURN)\n"));
+}
+
+#[test]
+fn test_code_writer_labels() {
+    // labels are scoped to the function they are in
+    let mut writer = CodeWriter::new();
+    let label = VmCommand::Label("LOOP".to_string());
+    assert_eq!("// label LOOP\n(LOOP)", writer.write("Main", &label));
+    writer.write("Main", &VmCommand::Function { name: "Main.f".to_string(), locals: 0 });
+    assert_eq!("// label LOOP\n(Main.f$LOOP)", writer.write("Main", &label));
+    assert_eq!("// goto LOOP\n@Main.f$LOOP\n0;JMP", writer.write("Main", &VmCommand::Goto("LOOP".to_string())));
+    writer.write("Main", &VmCommand::Function { name: "Main.g".to_string(), locals: 0 });
+    assert!(writer.write("Main", &VmCommand::IfGoto("LOOP".to_string())).ends_with("@Main.g$LOOP\nD;JNE"));
+    writer.start_file();
+    assert_eq!("// label LOOP\n(LOOP)", writer.write("Sys", &label));
+}
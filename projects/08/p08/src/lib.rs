@@ -0,0 +1,354 @@
+// VM Translator (VM code -> Hack assembly) for the Nand2Tetris Hack Computer
+// Author: Leo Robinovitch
+//
+// Each .vm file is parsed once into VmCommands, which code generation
+// then turns into Hack assembly.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub mod check;
+pub mod codegen;
+pub mod parser;
+pub mod prune;
+
+use codegen::CodeWriter;
+use parser::VmCommand;
+
+/// File extension of VM code
+pub const VM_EXTENSION: &str = "vm";
+
+
+/// A named .vm source
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Source {
+    /// path of the file, used in comments and errors
+    pub name: String,
+    pub contents: String,
+}
+
+
+/// Translator options
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Options {
+    /// start with code that sets up the stack and calls Sys.init
+    pub bootstrap: bool,
+    /// the sources are the whole program, so every called function must be defined
+    pub whole_program: bool,
+    /// leave out the functions that can never be called from Sys.init
+    pub prune: bool,
+    /// jump to shared call and return routines instead of inlining them,
+    /// which makes the code much smaller but slower
+    pub compact: bool,
+}
+
+
+/// The kind of problem found in VM code
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ErrorKind {
+    UnknownCommand,
+    WrongOperands,
+    UnknownSegment,
+    InvalidNumber,
+    NumberOutOfRange,
+    PopConstant,
+    InvalidSymbol,
+    DuplicateFunction,
+    MissingSysInit,
+    UndefinedFunction,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            ErrorKind::UnknownCommand => "unknown VM command",
+            ErrorKind::WrongOperands => "wrong number of operands for VM command",
+            ErrorKind::UnknownSegment => "unknown memory segment",
+            ErrorKind::InvalidNumber => "invalid number",
+            ErrorKind::NumberOutOfRange => "number out of range for the segment or command, found",
+            ErrorKind::PopConstant => "cannot pop to the constant segment",
+            ErrorKind::InvalidSymbol => "invalid label or function name",
+            ErrorKind::DuplicateFunction => "function defined more than once",
+            ErrorKind::MissingSysInit => "the program starts at a function that is not defined",
+            ErrorKind::UndefinedFunction => "call to a function that is not defined",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+
+/// Error returned by `translate`, located in the source
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct TranslateError {
+    pub kind: ErrorKind,
+    /// name of the file the error is in
+    pub file: String,
+    /// line number in the file, starting at 1, or 0 for errors of the whole program
+    pub line: usize,
+    /// the offending text
+    pub text: String,
+}
+
+impl fmt::Display for TranslateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.file.is_empty() {
+            write!(f, "{}:", self.file)?;
+        }
+        if self.line != 0 {
+            write!(f, "{}: ", self.line)?;
+        }
+        write!(f, "{} `{}`", self.kind, self.text)
+    }
+}
+
+impl Error for TranslateError {}
+
+
+/// The parsed commands of a .vm file
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct VmFile {
+    /// path of the file, used in comments and errors
+    pub name: String,
+    /// file name without directory or extension, which prefixes its static variables
+    pub static_name: String,
+    /// commands with their line numbers
+    pub commands: Vec<(usize, VmCommand)>,
+}
+
+
+/// Returns a cleaned string slice after removing comments and white space
+///
+/// # Arguments
+///
+/// * `line` - the raw line
+fn remove_comments(line: &str) -> &str {
+
+    // find the index where comments begin on the line
+    let idx_comment = match line.find("//") {
+        Some(idx) => idx,
+        _ => line.len()
+    };
+
+    // return a reference to the reduced str with no start/end whitespace
+    // note that memory contents are the same, just pointer and/or len changed
+    line[0..idx_comment].trim()
+}
+
+#[test]
+fn test_stripped_line() {
+    assert_eq!("", remove_comments(""));
+    assert_eq!("", remove_comments("    "));
+    assert_eq!("", remove_comments("//   "));
+    assert_eq!("nand2tetris", remove_comments("nand2tetris   // is so cool"));
+    assert_eq!("nand2tetris is so cool", remove_comments("nand2tetris is so cool // eh?"));
+}
+
+
+/// Get file name from path, without directory or extension
+///
+/// # Arguments
+///
+/// * `path` - path
+fn get_file_name(path: &str) -> String {
+    match Path::new(path).file_stem() {
+        Some(stem) => stem.to_string_lossy().into_owned(),
+        None => path.to_string()
+    }
+}
+
+#[test]
+fn test_get_file_name() {
+    assert_eq!("test", get_file_name("/asdfasdf/asdfasdf/test.vm"));
+    assert_eq!("test", get_file_name("/test.vm"));
+    assert_eq!("test", get_file_name("test.vm"));
+    assert_eq!("test", get_file_name("test"));
+}
+
+
+/// Parses a .vm source into commands, returning every error found
+///
+/// # Arguments
+///
+/// * `source` - named .vm source
+pub fn parse_source(source: &Source) -> Result<VmFile, Vec<TranslateError>> {
+    let mut commands: Vec<(usize, VmCommand)> = Vec::new();
+    let mut errors: Vec<TranslateError> = Vec::new();
+    for (idx, line) in source.contents.lines().enumerate() {
+        let clean_line = remove_comments(line);
+        if clean_line.is_empty() { continue };
+
+        match parser::parse_command(clean_line) {
+            Ok(command) => commands.push((idx + 1, command)),
+            Err((kind, text)) => errors.push(TranslateError {
+                kind,
+                file: source.name.clone(),
+                line: idx + 1,
+                text: text.to_string(),
+            })
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(VmFile { name: source.name.clone(), static_name: get_file_name(&source.name), commands })
+    } else {
+        Err(errors)
+    }
+}
+
+
+/// Returns the Hack assembly for parsed .vm files, in order
+///
+/// # Arguments
+///
+/// * `files` - parsed .vm files
+/// * `options` - translator options
+pub fn generate(files: &[VmFile], options: &Options) -> String {
+    let mut writer = if options.compact { CodeWriter::compact() } else { CodeWriter::new() };
+    let mut asm_code = String::new();
+    if options.bootstrap {
+        asm_code.push('\n');
+        asm_code.push_str(&writer.bootstrap());
+        asm_code.push('\n');
+    }
+    for file in files {
+        asm_code.push_str(&format!("\n// {}\n\n", file.name));
+        writer.start_file();
+        for (_, command) in &file.commands {
+            asm_code.push_str(&writer.write(&file.static_name, command));
+            asm_code.push('\n');
+        }
+    }
+
+    // shared routines go last, so that no code falls through into them
+    let routines = writer.routines();
+    if !routines.is_empty() {
+        asm_code.push_str(&format!("\n{}", routines));
+    }
+    asm_code
+}
+
+
+/// Translates .vm sources into one Hack assembly program, returning every
+/// error found if any source is invalid or the sources do not make up a
+/// valid program
+///
+/// # Arguments
+///
+/// * `sources` - named .vm sources
+/// * `options` - translator options
+pub fn translate(sources: &[Source], options: &Options) -> Result<String, Vec<TranslateError>> {
+    let mut files: Vec<VmFile> = Vec::new();
+    let mut errors: Vec<TranslateError> = Vec::new();
+    for source in sources {
+        match parse_source(source) {
+            Ok(file) => files.push(file),
+            Err(file_errors) => errors.extend(file_errors)
+        }
+    }
+
+    if errors.is_empty() {
+        errors = check::check_program(&files, options);
+    }
+    if errors.is_empty() {
+        if options.prune {
+            files = prune::prune(files);
+        }
+        Ok(generate(&files, options))
+    } else {
+        Err(errors)
+    }
+}
+
+#[test]
+fn test_translate() {
+    let sources = vec![
+        Source { name: "dir/Main.vm".to_string(), contents: "// comment\r\npush static 1 // x\r\npop that 0\r\n".to_string() },
+        Source { name: "dir/Sys.vm".to_string(), contents: "function Sys.init 0\ncall Main.f 0\n".to_string() },
+    ];
+    let asm_code = translate(&sources, &Options { bootstrap: true, ..Options::default() }).unwrap();
+    assert!(asm_code.starts_with("\n// Bootstrap\n\n@256\nD=A\n@SP\nM=D\n// call Sys.init 0\n"));
+    assert!(asm_code.contains("\n// dir/Main.vm\n\n// push static 1\n@Main.1\nD=M\n"));
+    assert!(asm_code.contains("\n// dir/Sys.vm\n\n// function Sys.init 0\n(Sys.init)\n// call Main.f 0\n"));
+    assert!(!translate(&sources, &Options::default()).unwrap().contains("Bootstrap"));
+    let compact = translate(&sources, &Options { bootstrap: true, compact: true, ..Options::default() }).unwrap();
+    assert!(compact.contains("\n// call Main.f 0\n@0\nD=A\n@R13\nM=D\n@Main.f\nD=A\n@R14\nM=D\n"));
+    assert!(compact.ends_with("@LCL\nM=D\n@R14\nA=M\n0;JMP\n"));
+    assert!(!compact.contains("($$RETURN)"));
+
+    // commands outside of functions are never pruned
+    assert_eq!(asm_code, translate(&sources, &Options { bootstrap: true, prune: true, ..Options::default() }).unwrap());
+
+    let sources = vec![
+        Source { name: "Main.vm".to_string(), contents: "push constant 1\npush local\n".to_string() },
+        Source { name: "Sys.vm".to_string(), contents: "pop constant 1\njump\n".to_string() },
+    ];
+    let errors = translate(&sources, &Options::default()).unwrap_err();
+    let found: Vec<(ErrorKind, &str, usize)> = errors.iter().map(|e| (e.kind, e.file.as_str(), e.line)).collect();
+    assert_eq!(vec![
+        (ErrorKind::WrongOperands, "Main.vm", 2),
+        (ErrorKind::PopConstant, "Sys.vm", 1),
+        (ErrorKind::UnknownCommand, "Sys.vm", 2),
+    ], found);
+    assert_eq!("Sys.vm:2: unknown VM command `jump`", errors[2].to_string());
+
+    let sources = vec![
+        Source { name: "Main.vm".to_string(), contents: "function Main.main 0
+call Main.mian 0
+".to_string() },
+        Source { name: "Sys.vm".to_string(), contents: "function Sys.init 0
+call Main.main 0
+".to_string() },
+    ];
+    let errors = translate(&sources, &Options { bootstrap: true, whole_program: true, ..Options::default() }).unwrap_err();
+    assert_eq!(vec!["Main.vm:2: call to a function that is not defined `Main.mian`"],
+               errors.iter().map(|e| e.to_string()).collect::<Vec<String>>());
+}
+
+
+/// Returns the .vm files to translate: the file itself for a .vm file,
+/// or the .vm files in a directory, sorted by name
+///
+/// # Arguments
+///
+/// * `path` - a .vm file or a directory
+pub fn vm_paths(path: &Path) -> io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == VM_EXTENSION) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+
+/// Returns the path of the .asm output: next to a .vm file, or in a
+/// directory and named after it
+///
+/// # Arguments
+///
+/// * `path` - a .vm file or a directory
+pub fn out_path(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        let name = path.canonicalize().ok()
+            .and_then(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+        path.join(format!("{}.asm", name))
+    } else {
+        path.with_extension("asm")
+    }
+}
+
+#[test]
+fn test_out_path() {
+    assert_eq!(PathBuf::from("dir/Main.asm"), out_path(Path::new("dir/Main.vm")));
+    assert_eq!(PathBuf::from("src/src.asm"), out_path(Path::new("src")));
+}
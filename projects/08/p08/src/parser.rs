@@ -0,0 +1,324 @@
+// Parser for VM commands, run once on each stripped line of a .vm file
+// Author: Leo Robinovitch
+
+use std::fmt;
+
+use crate::ErrorKind;
+
+/// Largest value `push constant` can load, the largest Hack A command value
+pub const MAX_CONSTANT: u16 = 32767;
+
+
+/// A memory segment of push and pop commands
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Segment {
+    Constant,
+    Local,
+    Argument,
+    This,
+    That,
+    Temp,
+    Pointer,
+    Static,
+}
+
+impl Segment {
+    /// Returns the segment with the given name, e.g. `local`
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - name of the segment in VM code
+    pub fn from_name(name: &str) -> Option<Segment> {
+        match name {
+            "constant" => Some(Segment::Constant),
+            "local" => Some(Segment::Local),
+            "argument" => Some(Segment::Argument),
+            "this" => Some(Segment::This),
+            "that" => Some(Segment::That),
+            "temp" => Some(Segment::Temp),
+            "pointer" => Some(Segment::Pointer),
+            "static" => Some(Segment::Static),
+            _ => None
+        }
+    }
+
+    /// Returns the name of the segment in VM code
+    pub fn name(self) -> &'static str {
+        match self {
+            Segment::Constant => "constant",
+            Segment::Local => "local",
+            Segment::Argument => "argument",
+            Segment::This => "this",
+            Segment::That => "that",
+            Segment::Temp => "temp",
+            Segment::Pointer => "pointer",
+            Segment::Static => "static",
+        }
+    }
+
+    /// Returns the largest index of the segment
+    fn max_index(self) -> u16 {
+        match self {
+            Segment::Temp => 7,
+            Segment::Pointer => 1,
+            _ => MAX_CONSTANT
+        }
+    }
+}
+
+
+/// An arithmetic or logical command, which works on the top of the stack
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Op {
+    Add,
+    Sub,
+    Neg,
+    Eq,
+    Gt,
+    Lt,
+    And,
+    Or,
+    Not,
+}
+
+impl Op {
+    /// Returns the command with the given name, e.g. `add`
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - name of the command in VM code
+    pub fn from_name(name: &str) -> Option<Op> {
+        match name {
+            "add" => Some(Op::Add),
+            "sub" => Some(Op::Sub),
+            "neg" => Some(Op::Neg),
+            "eq" => Some(Op::Eq),
+            "gt" => Some(Op::Gt),
+            "lt" => Some(Op::Lt),
+            "and" => Some(Op::And),
+            "or" => Some(Op::Or),
+            "not" => Some(Op::Not),
+            _ => None
+        }
+    }
+
+    /// Returns the name of the command in VM code
+    pub fn name(self) -> &'static str {
+        match self {
+            Op::Add => "add",
+            Op::Sub => "sub",
+            Op::Neg => "neg",
+            Op::Eq => "eq",
+            Op::Gt => "gt",
+            Op::Lt => "lt",
+            Op::And => "and",
+            Op::Or => "or",
+            Op::Not => "not",
+        }
+    }
+}
+
+#[test]
+fn test_op_from_name() {
+    for name in &["add", "sub", "neg", "eq", "gt", "lt", "and", "or", "not"] {
+        assert_eq!(Some(*name), Op::from_name(name).map(Op::name));
+    }
+    assert_eq!(None, Op::from_name("butt"));
+    assert_eq!(None, Op::from_name("add sub"));
+}
+
+
+/// A parsed VM command
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum VmCommand {
+    Push(Segment, u16),
+    Pop(Segment, u16),
+    Arith(Op),
+    Label(String),
+    Goto(String),
+    IfGoto(String),
+    Function { name: String, locals: u16 },
+    Call { name: String, args: u16 },
+    Return,
+}
+
+impl fmt::Display for VmCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmCommand::Push(segment, index) => write!(f, "push {} {}", segment.name(), index),
+            VmCommand::Pop(segment, index) => write!(f, "pop {} {}", segment.name(), index),
+            VmCommand::Arith(op) => write!(f, "{}", op.name()),
+            VmCommand::Label(label) => write!(f, "label {}", label),
+            VmCommand::Goto(label) => write!(f, "goto {}", label),
+            VmCommand::IfGoto(label) => write!(f, "if-goto {}", label),
+            VmCommand::Function { name, locals } => write!(f, "function {} {}", name, locals),
+            VmCommand::Call { name, args } => write!(f, "call {} {}", name, args),
+            VmCommand::Return => write!(f, "return"),
+        }
+    }
+}
+
+
+/// Checks if text is a valid label or function name: letters, digits,
+/// `_`, `.` and `:`, not starting with a digit
+///
+/// # Arguments
+///
+/// * `text` - the text to check
+fn is_symbol(text: &str) -> bool {
+    match text.chars().next() {
+        Some(first) => {
+            !first.is_ascii_digit()
+                && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == ':')
+        },
+        None => false
+    }
+}
+
+#[test]
+fn test_is_symbol() {
+    assert!(is_symbol("Main.fibonacci"));
+    assert!(is_symbol("WHILE_END:0"));
+    assert!(!is_symbol("1loop"));
+    assert!(!is_symbol("a$b"));
+    assert!(!is_symbol(""));
+}
+
+
+/// Returns the label or function name operand of a command
+///
+/// # Arguments
+///
+/// * `text` - the operand
+fn parse_symbol(text: &str) -> Result<String, (ErrorKind, &str)> {
+    if is_symbol(text) {
+        Ok(text.to_string())
+    } else {
+        Err((ErrorKind::InvalidSymbol, text))
+    }
+}
+
+
+/// Returns a number operand of a command, which is at most `max`
+///
+/// # Arguments
+///
+/// * `text` - the operand
+/// * `max` - largest allowed value
+fn parse_number(text: &str, max: u16) -> Result<u16, (ErrorKind, &str)> {
+    if text.is_empty() || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return Err((ErrorKind::InvalidNumber, text));
+    }
+    match text.parse::<u16>() {
+        Ok(value) if value <= max => Ok(value),
+        _ => Err((ErrorKind::NumberOutOfRange, text))
+    }
+}
+
+
+/// Returns the segment and index of a push or pop command
+///
+/// # Arguments
+///
+/// * `segment` - the segment operand, e.g. `local`
+/// * `index` - the index operand
+fn parse_segment<'a>(segment: &'a str, index: &'a str) -> Result<(Segment, u16), (ErrorKind, &'a str)> {
+    let segment = Segment::from_name(segment).ok_or((ErrorKind::UnknownSegment, segment))?;
+    Ok((segment, parse_number(index, segment.max_index())?))
+}
+
+#[test]
+fn test_parse_push_pop() {
+    assert_eq!(Ok((Segment::Static, 1)), parse_segment("static", "1"));
+    assert_eq!(Ok((Segment::Local, 10)), parse_segment("local", "10"));
+    for name in &["constant", "local", "argument", "this", "that", "temp", "pointer", "static"] {
+        assert_eq!(Some(*name), Segment::from_name(name).map(Segment::name));
+    }
+    assert_eq!(None, Segment::from_name("Local"));
+
+    // edges of the fixed-size segments and of constants
+    assert_eq!(Ok((Segment::Temp, 0)), parse_segment("temp", "0"));
+    assert_eq!(Ok((Segment::Temp, 7)), parse_segment("temp", "7"));
+    assert_eq!(Ok((Segment::Pointer, 1)), parse_segment("pointer", "1"));
+    assert_eq!(Ok((Segment::Constant, MAX_CONSTANT)), parse_segment("constant", "32767"));
+    assert_eq!(Ok((Segment::Constant, 7)), parse_segment("constant", "007"));
+    assert_eq!(Err((ErrorKind::NumberOutOfRange, "65536")), parse_segment("static", "65536"));
+    assert_eq!(Err((ErrorKind::InvalidNumber, "+1")), parse_segment("local", "+1"));
+    assert_eq!(Err((ErrorKind::InvalidNumber, "x")), parse_segment("local", "x"));
+    assert_eq!(Err((ErrorKind::UnknownSegment, "stack")), parse_segment("stack", "0"));
+}
+
+
+/// Parses a stripped line into a VM command. On error, returns the kind of
+/// error and the offending slice of the line.
+///
+/// # Arguments
+///
+/// * `line` - the stripped line, e.g. `push constant 7`
+pub fn parse_command(line: &str) -> Result<VmCommand, (ErrorKind, &str)> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        [name] if Op::from_name(name).is_some() => Ok(VmCommand::Arith(Op::from_name(name).unwrap())),
+        ["push", segment, index] => parse_segment(segment, index).map(|(segment, index)| VmCommand::Push(segment, index)),
+        ["pop", "constant", _] => Err((ErrorKind::PopConstant, line)),
+        ["pop", segment, index] => parse_segment(segment, index).map(|(segment, index)| VmCommand::Pop(segment, index)),
+        ["label", label] => parse_symbol(label).map(VmCommand::Label),
+        ["goto", label] => parse_symbol(label).map(VmCommand::Goto),
+        ["if-goto", label] => parse_symbol(label).map(VmCommand::IfGoto),
+        ["function", name, locals] => Ok(VmCommand::Function {
+            name: parse_symbol(name)?,
+            locals: parse_number(locals, MAX_CONSTANT)?,
+        }),
+        ["call", name, args] => Ok(VmCommand::Call {
+            name: parse_symbol(name)?,
+            args: parse_number(args, MAX_CONSTANT)?,
+        }),
+        ["return"] => Ok(VmCommand::Return),
+        [name, ..] if ["push", "pop", "label", "goto", "if-goto", "function", "call", "return"].contains(name) => {
+            Err((ErrorKind::WrongOperands, line))
+        },
+        _ => Err((ErrorKind::UnknownCommand, line))
+    }
+}
+
+#[test]
+fn test_parse_command() {
+    assert_eq!(Ok(VmCommand::Push(Segment::Constant, 7)), parse_command("push constant 7"));
+    assert_eq!(Ok(VmCommand::Pop(Segment::Local, 10)), parse_command("pop  local\t10"));
+    assert_eq!(Ok(VmCommand::Push(Segment::Static, 1)), parse_command("push static 1"));
+    assert_eq!(Ok(VmCommand::Arith(Op::Eq)), parse_command("eq"));
+    assert_eq!(Ok(VmCommand::Label("LOOP_START".to_string())), parse_command("label LOOP_START"));
+    assert_eq!(Ok(VmCommand::IfGoto("END".to_string())), parse_command("if-goto END"));
+    assert_eq!(Ok(VmCommand::Function { name: "Main.main".to_string(), locals: 2 }), parse_command("function Main.main 2"));
+    assert_eq!(Ok(VmCommand::Call { name: "Math.multiply".to_string(), args: 2 }), parse_command("call Math.multiply 2"));
+    assert_eq!(Ok(VmCommand::Return), parse_command("return"));
+
+    assert_eq!(Err((ErrorKind::UnknownCommand, "butt")), parse_command("butt"));
+    assert_eq!(Err((ErrorKind::WrongOperands, "push constant")), parse_command("push constant"));
+    assert_eq!(Err((ErrorKind::UnknownSegment, "heap")), parse_command("push heap 1"));
+    assert_eq!(Err((ErrorKind::InvalidNumber, "-1")), parse_command("push constant -1"));
+    assert_eq!(Err((ErrorKind::NumberOutOfRange, "32768")), parse_command("push constant 32768"));
+    assert_eq!(Err((ErrorKind::NumberOutOfRange, "8")), parse_command("pop temp 8"));
+    assert_eq!(Err((ErrorKind::NumberOutOfRange, "2")), parse_command("push pointer 2"));
+    assert_eq!(Err((ErrorKind::PopConstant, "pop constant 1")), parse_command("pop constant 1"));
+    assert_eq!(Err((ErrorKind::InvalidSymbol, "1f")), parse_command("goto 1f"));
+}
+
+#[test]
+fn test_command_type() {
+    assert!(matches!(parse_command("push constant 1"), Ok(VmCommand::Push(..))));
+    assert!(matches!(parse_command("pop local 1"), Ok(VmCommand::Pop(..))));
+    assert!(matches!(parse_command("eq"), Ok(VmCommand::Arith(Op::Eq))));
+    assert_eq!(Err((ErrorKind::UnknownCommand, "pushconstant 1")), parse_command("pushconstant 1"));
+    assert_eq!(Err((ErrorKind::WrongOperands, "push constant 1 2")), parse_command("push constant 1 2"));
+    assert_eq!(Err((ErrorKind::WrongOperands, "pop")), parse_command("pop"));
+    assert_eq!(Err((ErrorKind::UnknownCommand, "eq 1")), parse_command("eq 1"));
+}
+
+#[test]
+fn test_display() {
+    for line in &["push constant 7", "pop that 2", "not", "label A.b:1", "goto A", "if-goto A",
+                  "function Main.main 2", "call Main.main 0", "return"] {
+        assert_eq!(*line, parse_command(line).unwrap().to_string());
+    }
+}
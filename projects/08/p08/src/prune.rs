@@ -0,0 +1,124 @@
+// Dead-function elimination, which drops the functions the program can
+// never call starting from Sys.init
+// Author: Leo Robinovitch
+
+use std::collections::{HashMap, HashSet};
+
+use crate::codegen::ENTRY_FUNCTION;
+use crate::parser::VmCommand;
+use crate::VmFile;
+
+
+/// Returns the functions each function calls, in the order of the calls,
+/// and the functions called by commands before the first function of a file
+///
+/// # Arguments
+///
+/// * `files` - parsed .vm files
+fn call_graph(files: &[VmFile]) -> (HashMap<&str, Vec<&str>>, Vec<&str>) {
+    let mut graph: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut top_level: Vec<&str> = Vec::new();
+    for file in files {
+        let mut function: Option<&str> = None;
+        for (_, command) in &file.commands {
+            match command {
+                VmCommand::Function { name, .. } => {
+                    function = Some(name);
+                    graph.entry(name).or_default();
+                },
+                VmCommand::Call { name, .. } => match function {
+                    Some(function) => graph.entry(function).or_default().push(name),
+                    None => top_level.push(name)
+                },
+                _ => ()
+            }
+        }
+    }
+    (graph, top_level)
+}
+
+
+/// Returns the names of the functions reachable through calls from Sys.init
+/// and from the commands outside of functions, which are never pruned,
+/// including Sys.init itself if it is defined
+///
+/// # Arguments
+///
+/// * `files` - parsed .vm files
+pub fn reachable_functions(files: &[VmFile]) -> HashSet<String> {
+    let (graph, top_level) = call_graph(files);
+    let mut reached: HashSet<String> = HashSet::new();
+    let mut pending: Vec<&str> = vec![ENTRY_FUNCTION];
+    pending.extend(top_level);
+    while let Some(name) = pending.pop() {
+        if let Some(callees) = graph.get(name) {
+            if reached.insert(name.to_string()) {
+                pending.extend(callees);
+            }
+        }
+    }
+    reached
+}
+
+
+/// Returns the .vm files without the functions that can never be called,
+/// dropping files left with no commands. Commands before the first function
+/// of a file are kept.
+///
+/// # Arguments
+///
+/// * `files` - parsed .vm files
+pub fn prune(files: Vec<VmFile>) -> Vec<VmFile> {
+    let reached = reachable_functions(&files);
+    files.into_iter()
+        .map(|file| {
+            let mut keep = true;
+            let commands = file.commands.into_iter()
+                .filter(|(_, command)| {
+                    if let VmCommand::Function { name, .. } = command {
+                        keep = reached.contains(name);
+                    }
+                    keep
+                })
+                .collect();
+            VmFile { commands, ..file }
+        })
+        .filter(|file| !file.commands.is_empty())
+        .collect()
+}
+
+#[test]
+fn test_prune() {
+    use crate::{parse_source, Source};
+
+    let parse = |name: &str, contents: &str| {
+        parse_source(&Source { name: name.to_string(), contents: contents.to_string() }).unwrap()
+    };
+    let files = vec![
+        parse("Main.vm", "function Main.main 0\ncall Math.abs 1\nreturn\nfunction Main.unused 0\ncall Math.max 2\nreturn\n"),
+        parse("Math.vm", "function Math.abs 0\ncall Math.abs 1\nreturn\nfunction Math.max 0\nreturn\n"),
+        parse("Screen.vm", "function Screen.clear 0\nreturn\n"),
+        parse("Sys.vm", "function Sys.init 0\ncall Main.main 0\nlabel END\ngoto END\n"),
+    ];
+    let mut reached: Vec<String> = reachable_functions(&files).into_iter().collect();
+    reached.sort();
+    assert_eq!(vec!["Main.main", "Math.abs", "Sys.init"], reached);
+
+    let pruned = prune(files);
+    assert_eq!(vec!["Main.vm", "Math.vm", "Sys.vm"], pruned.iter().map(|file| file.name.as_str()).collect::<Vec<&str>>());
+    assert_eq!(3, pruned[0].commands.len());
+    assert_eq!((3, VmCommand::Return), pruned[1].commands[2]);
+    assert_eq!(4, pruned[2].commands.len());
+
+    // functions called only by code outside of functions are kept
+    let files = vec![
+        parse("Main.vm", "push constant 3\ncall Main.square 1\nfunction Main.square 0\nreturn\nfunction Main.unused 0\nreturn\n"),
+        parse("Sys.vm", "function Sys.init 0\nlabel END\ngoto END\n"),
+    ];
+    let mut reached: Vec<String> = reachable_functions(&files).into_iter().collect();
+    reached.sort();
+    assert_eq!(vec!["Main.square", "Sys.init"], reached);
+    let pruned = prune(files);
+    assert_eq!(4, pruned[0].commands.len());
+    assert_eq!((3, VmCommand::Function { name: "Main.square".to_string(), locals: 0 }), pruned[0].commands[2]);
+}